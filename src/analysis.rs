@@ -0,0 +1,362 @@
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::FSMState;
+
+type StateRef = Rc<RefCell<FSMState>>;
+
+// Breadth-first search from `from`, returning the states along a path with the
+// fewest transitions to `to`, both ends included.
+pub fn shortest_path(from: &StateRef, to: &StateRef) -> Option<Vec<StateRef>> {
+    let mut parents: HashMap<*const RefCell<FSMState>, Option<StateRef>> = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    parents.insert(Rc::as_ptr(from), None);
+    queue.push_back(from.clone());
+
+    while let Some(state) = queue.pop_front() {
+        if Rc::ptr_eq(&state, to) {
+            let mut path = vec![state];
+
+            while let Some(Some(parent)) = parents.get(&Rc::as_ptr(path.last().unwrap())) {
+                path.push(parent.clone());
+            }
+
+            path.reverse();
+            return Some(path);
+        }
+
+        for next in state
+            .borrow()
+            .next_states
+            .iter()
+            .filter_map(|n| n.upgrade())
+        {
+            if let Entry::Vacant(entry) = parents.entry(Rc::as_ptr(&next)) {
+                entry.insert(Some(state.clone()));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+// Non-accepting states without any outgoing transition: once entered, the
+// machine can neither move on nor accept.
+pub fn deadlocks(states: &[StateRef]) -> Vec<StateRef> {
+    states
+        .iter()
+        .filter(|s| {
+            let s = s.borrow();
+            !s.accepting && s.next_states.iter().all(|n| n.strong_count() == 0)
+        })
+        .cloned()
+        .collect()
+}
+
+// States from which no accepting state can be reached, no matter which
+// transitions are taken. Found by walking transitions backwards from every
+// accepting state.
+pub fn cannot_accept(states: &[StateRef]) -> Vec<StateRef> {
+    let mut predecessors: HashMap<*const RefCell<FSMState>, Vec<StateRef>> = HashMap::new();
+    for state in states {
+        for next in state
+            .borrow()
+            .next_states
+            .iter()
+            .filter_map(|n| n.upgrade())
+        {
+            predecessors
+                .entry(Rc::as_ptr(&next))
+                .or_default()
+                .push(state.clone());
+        }
+    }
+
+    let mut live: HashSet<*const RefCell<FSMState>> = HashSet::new();
+    let mut queue: VecDeque<StateRef> = states
+        .iter()
+        .filter(|s| s.borrow().accepting)
+        .cloned()
+        .collect();
+    live.extend(queue.iter().map(Rc::as_ptr));
+
+    while let Some(state) = queue.pop_front() {
+        for previous in predecessors.get(&Rc::as_ptr(&state)).into_iter().flatten() {
+            if live.insert(Rc::as_ptr(previous)) {
+                queue.push_back(previous.clone());
+            }
+        }
+    }
+
+    states
+        .iter()
+        .filter(|s| !live.contains(&Rc::as_ptr(s)))
+        .cloned()
+        .collect()
+}
+
+// States with no transitions in or out, usually leftovers from editing.
+pub fn orphans(states: &[StateRef]) -> Vec<StateRef> {
+    let mut connected: HashSet<*const RefCell<FSMState>> = HashSet::new();
+    for state in states {
+        for next in state
+            .borrow()
+            .next_states
+            .iter()
+            .filter_map(|n| n.upgrade())
+        {
+            connected.insert(Rc::as_ptr(state));
+            connected.insert(Rc::as_ptr(&next));
+        }
+    }
+
+    states
+        .iter()
+        .filter(|s| !connected.contains(&Rc::as_ptr(s)))
+        .cloned()
+        .collect()
+}
+
+// Number of transitions on a shortest path from `initial` to every state it
+// can reach.
+pub fn depths(initial: &StateRef) -> HashMap<*const RefCell<FSMState>, usize> {
+    let mut depths = HashMap::from([(Rc::as_ptr(initial), 0)]);
+    let mut queue = VecDeque::from([initial.clone()]);
+
+    while let Some(state) = queue.pop_front() {
+        let depth = depths[&Rc::as_ptr(&state)];
+
+        for next in state
+            .borrow()
+            .next_states
+            .iter()
+            .filter_map(|n| n.upgrade())
+        {
+            if let Entry::Vacant(entry) = depths.entry(Rc::as_ptr(&next)) {
+                entry.insert(depth + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    depths
+}
+
+// Strongly connected components: groups of states that can all reach each
+// other. Every state is in exactly one, states on no cycle are alone in
+// theirs. Members keep their order from `states`.
+pub fn components(states: &[StateRef]) -> Vec<Vec<StateRef>> {
+    let index: HashMap<*const RefCell<FSMState>, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (Rc::as_ptr(s), i))
+        .collect();
+    let successors: Vec<Vec<usize>> = states
+        .iter()
+        .map(|s| {
+            s.borrow()
+                .next_states
+                .iter()
+                .filter_map(|n| index.get(&n.as_ptr()).copied())
+                .collect()
+        })
+        .collect();
+    let mut predecessors = vec![vec![]; states.len()];
+    for (from, nexts) in successors.iter().enumerate() {
+        for &to in nexts {
+            predecessors[to].push(from);
+        }
+    }
+
+    // Kosaraju: order states by when a depth-first search finishes them,
+    // then collect what reaches each one backwards, latest finished first.
+    let mut finished = vec![];
+    let mut visited = vec![false; states.len()];
+    for root in 0..states.len() {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+
+        while let Some((state, next)) = stack.pop() {
+            match successors[state].get(next) {
+                Some(&successor) => {
+                    stack.push((state, next + 1));
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => finished.push(state),
+            }
+        }
+    }
+
+    let mut component = vec![None; states.len()];
+    let mut count = 0;
+    for &root in finished.iter().rev() {
+        if component[root].is_some() {
+            continue;
+        }
+        component[root] = Some(count);
+        let mut stack = vec![root];
+
+        while let Some(state) = stack.pop() {
+            for &previous in &predecessors[state] {
+                if component[previous].is_none() {
+                    component[previous] = Some(count);
+                    stack.push(previous);
+                }
+            }
+        }
+        count += 1;
+    }
+
+    let mut components = vec![vec![]; count];
+    for (state, c) in states.iter().zip(component) {
+        components[c.unwrap_or_default()].push(state.clone());
+    }
+    components
+}
+
+// States on some path from `from` to `to`, both ends included: those `from`
+// reaches that can also reach `to`. Empty when `to` is unreachable.
+pub fn between(states: &[StateRef], from: &StateRef, to: &StateRef) -> Vec<StateRef> {
+    let reachable = depths(from);
+    if !reachable.contains_key(&Rc::as_ptr(to)) {
+        return vec![];
+    }
+
+    let mut predecessors: HashMap<*const RefCell<FSMState>, Vec<StateRef>> = HashMap::new();
+    for state in states {
+        for next in state
+            .borrow()
+            .next_states
+            .iter()
+            .filter_map(|n| n.upgrade())
+        {
+            predecessors
+                .entry(Rc::as_ptr(&next))
+                .or_default()
+                .push(state.clone());
+        }
+    }
+
+    let mut reaching = HashSet::from([Rc::as_ptr(to)]);
+    let mut queue = VecDeque::from([to.clone()]);
+    while let Some(state) = queue.pop_front() {
+        for previous in predecessors.get(&Rc::as_ptr(&state)).into_iter().flatten() {
+            if reachable.contains_key(&Rc::as_ptr(previous))
+                && reaching.insert(Rc::as_ptr(previous))
+            {
+                queue.push_back(previous.clone());
+            }
+        }
+    }
+
+    states
+        .iter()
+        .filter(|s| reaching.contains(&Rc::as_ptr(s)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine_file::Machine;
+
+    // A reaches the cycle B <-> C, which accepts, and the cycle D <-> E,
+    // which never does. F is on its own.
+    fn machine() -> Machine {
+        crate::import::mermaid(
+            "stateDiagram-v2\n[*] --> A\nA --> B\nB --> C\nC --> B\nC --> [*]\nA --> D\nD --> E\nE --> D\nstate F\n",
+        )
+        .unwrap()
+    }
+
+    fn find(machine: &Machine, name: &str) -> StateRef {
+        machine
+            .states
+            .iter()
+            .find(|s| s.borrow().name == name)
+            .unwrap()
+            .clone()
+    }
+
+    fn names(states: &[StateRef]) -> Vec<String> {
+        let mut names: Vec<_> = states.iter().map(|s| s.borrow().name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn finds_shortest_paths() {
+        let machine = machine();
+        let (a, c) = (find(&machine, "A"), find(&machine, "C"));
+
+        let path = shortest_path(&a, &c).unwrap();
+        let path: Vec<_> = path.iter().map(|s| s.borrow().name.clone()).collect();
+        assert_eq!(path, ["A", "B", "C"]);
+        assert_eq!(shortest_path(&a, &a).unwrap().len(), 1);
+        assert!(shortest_path(&c, &a).is_none());
+    }
+
+    #[test]
+    fn finds_deadlocks() {
+        assert_eq!(names(&deadlocks(&machine().states)), ["F"]);
+    }
+
+    #[test]
+    fn finds_states_that_cannot_accept() {
+        assert_eq!(names(&cannot_accept(&machine().states)), ["D", "E", "F"]);
+    }
+
+    #[test]
+    fn finds_orphans() {
+        assert_eq!(names(&orphans(&machine().states)), ["F"]);
+    }
+
+    #[test]
+    fn measures_depths() {
+        let machine = machine();
+        let depths = depths(&find(&machine, "A"));
+        let depth = |name| depths.get(&Rc::as_ptr(&find(&machine, name))).copied();
+
+        assert_eq!(depth("A"), Some(0));
+        assert_eq!(depth("B"), Some(1));
+        assert_eq!(depth("D"), Some(1));
+        assert_eq!(depth("C"), Some(2));
+        assert_eq!(depth("E"), Some(2));
+        assert_eq!(depth("F"), None);
+    }
+
+    #[test]
+    fn groups_cycles_into_components() {
+        let machine = machine();
+        let mut components: Vec<_> = components(&machine.states)
+            .iter()
+            .map(|c| names(c))
+            .collect();
+        components.sort();
+        assert_eq!(
+            components,
+            [vec!["A"], vec!["B", "C"], vec!["D", "E"], vec!["F"]]
+        );
+    }
+
+    #[test]
+    fn finds_states_between() {
+        let machine = machine();
+        let [a, b, c, e] = ["A", "B", "C", "E"].map(|n| find(&machine, n));
+
+        assert_eq!(names(&between(&machine.states, &a, &c)), ["A", "B", "C"]);
+        assert_eq!(names(&between(&machine.states, &a, &e)), ["A", "D", "E"]);
+        assert!(between(&machine.states, &b, &a).is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use regex_automata::meta::Regex;
+
+// Commands typed after `:`. Arguments are separated by spaces, and names
+// containing spaces can be written in double quotes.
+pub enum Command {
+    Write(Option<PathBuf>),
+    WriteQuit,
+    Quit { force: bool },
+    Edit { path: PathBuf, force: bool },
+    New(String),
+    Rename { from: String, to: String },
+    Delete(String),
+    Snapshot(String),
+    Restore(String),
+    Diff(Option<String>),
+    Snapshots,
+    // Sets or, without text, removes the note on the selected transition.
+    Note(Option<String>),
+    // Merges the connection source into the selected state.
+    Merge,
+    // Copies the selected state under a new name, then asks which incoming
+    // transitions should lead to the copy.
+    Split(String),
+    // Adds a state in the middle of the selected transition.
+    Insert(String),
+    // Replaces text in state names and transition notes, after a preview.
+    Replace { find: Find, with: String },
+    // Set the document's metadata, or clear it without text.
+    Title(String),
+    Author(String),
+    Description(String),
+    // Write the active key bindings and styles to a profile file, or take
+    // them over from one.
+    ExportProfile(PathBuf),
+    ImportProfile(PathBuf),
+    // Writes an SVG or DOT diagram, optionally with a legend.
+    Export { path: PathBuf, legend: bool },
+    // Starts or leaves the tutorial.
+    Tutorial,
+}
+
+fn split(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch == '"' {
+            chars.next();
+            let word: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(&ch) = chars.peek().filter(|c| !c.is_whitespace()) {
+                word.push(ch);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+
+    if line.matches('"').count() % 2 == 1 {
+        return Err(t!("command.unterminated_quote"));
+    }
+
+    Ok(words)
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let words = split(line)?;
+    let args: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    Ok(match args.as_slice() {
+        ["w"] => Command::Write(None),
+        ["w", path] => Command::Write(Some(path.into())),
+        ["wq" | "x"] => Command::WriteQuit,
+        ["q"] => Command::Quit { force: false },
+        ["q!"] => Command::Quit { force: true },
+        ["e", path] => Command::Edit {
+            path: path.into(),
+            force: false,
+        },
+        ["e!", path] => Command::Edit {
+            path: path.into(),
+            force: true,
+        },
+        ["new", name] => Command::New(name.to_string()),
+        ["rename", from, to] => Command::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        },
+        ["delete", pattern] => Command::Delete(pattern.to_string()),
+        ["snapshot", name] => Command::Snapshot(name.to_string()),
+        ["restore", name] => Command::Restore(name.to_string()),
+        ["diff"] => Command::Diff(None),
+        ["diff", name] => Command::Diff(Some(name.to_string())),
+        ["snapshots"] => Command::Snapshots,
+        ["merge"] => Command::Merge,
+        ["split", name] => Command::Split(name.to_string()),
+        ["insert", name] => Command::Insert(name.to_string()),
+        ["replace", find, with] => Command::Replace {
+            find: Find::parse(find)?,
+            with: with.to_string(),
+        },
+        ["title", text @ ..] => Command::Title(text.join(" ")),
+        ["author", text @ ..] => Command::Author(text.join(" ")),
+        ["description", text @ ..] => Command::Description(text.join(" ")),
+        ["export-profile", path] => Command::ExportProfile(path.into()),
+        ["export", path] => Command::Export {
+            path: path.into(),
+            legend: false,
+        },
+        ["export", path, "legend"] => Command::Export {
+            path: path.into(),
+            legend: true,
+        },
+        ["import-profile", path] => Command::ImportProfile(path.into()),
+        ["tutorial"] => Command::Tutorial,
+        ["note"] => Command::Note(None),
+        ["note", text @ ..] => Command::Note(Some(text.join(" "))),
+        [] => return Err(t!("command.empty")),
+        [name, ..] => return Err(t!("command.unknown", name = name)),
+    })
+}
+
+// What `replace` looks for: text, or a regular expression written between
+// slashes like `/S(\d+)/`, whose replacement can refer to groups as `$1`.
+pub enum Find {
+    Text(String),
+    Pattern(String, Regex),
+}
+
+impl Find {
+    fn parse(find: &str) -> Result<Self, String> {
+        match find.strip_prefix('/').and_then(|f| f.strip_suffix('/')) {
+            Some(pattern) => Regex::new(pattern)
+                .map(|regex| Self::Pattern(pattern.to_string(), regex))
+                // The syntax error itself is on the last line of the source,
+                // under a drawing of where it is.
+                .map_err(|e| {
+                    let source = std::error::Error::source(&e).map_or(e.to_string(), |s| {
+                        s.to_string().lines().last().unwrap_or_default().to_string()
+                    });
+                    t!(
+                        "command.invalid_pattern",
+                        pattern = format!("{pattern:?}"),
+                        error = source
+                    )
+                }),
+            None => Ok(Self::Text(find.to_string())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Text(text) => text.is_empty(),
+            Self::Pattern(pattern, _) => pattern.is_empty(),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Self::Text(find) => text.contains(find.as_str()),
+            Self::Pattern(_, regex) => regex.is_match(text),
+        }
+    }
+
+    pub fn replace(&self, text: &str, with: &str) -> String {
+        let regex = match self {
+            Self::Text(find) => return text.replace(find.as_str(), with),
+            Self::Pattern(_, regex) => regex,
+        };
+
+        let mut replaced = String::new();
+        let mut last = 0;
+        for captures in regex.captures_iter(text) {
+            let Some(found) = captures.get_match() else {
+                continue;
+            };
+            replaced.push_str(&text[last..found.start()]);
+            captures.interpolate_string_into(text, with, &mut replaced);
+            last = found.end();
+        }
+        replaced.push_str(&text[last..]);
+        replaced
+    }
+}
+
+impl fmt::Display for Find {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{text:?}"),
+            Self::Pattern(pattern, _) => write!(f, "/{pattern}/"),
+        }
+    }
+}
+
+// Shell-style matching where `*` stands for any run of characters and `?` for
+// exactly one.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Position in the pattern right after the last `*`, and the position in
+    // the name it was tried against, for backtracking.
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str) -> Find {
+        match parse(&format!("replace {text} x")) {
+            Ok(Command::Replace { find, .. }) => find,
+            _ => panic!("{text} should parse"),
+        }
+    }
+
+    #[test]
+    fn replaces_text() {
+        let find = find("S");
+        assert!(matches!(find, Find::Text(_)));
+        assert_eq!(find.replace("S1 S2", "Q"), "Q1 Q2");
+    }
+
+    #[test]
+    fn replaces_patterns_with_groups() {
+        let find = find(r"/S(\d+)/");
+        assert!(find.is_match("S12"));
+        assert!(!find.is_match("S"));
+        assert_eq!(find.replace("S1 and S23", "Q$1"), "Q1 and Q23");
+        assert_eq!(find.to_string(), r"/S(\d+)/");
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(parse("replace /(/ x").is_err());
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(parse("w"), Ok(Command::Write(None))));
+        assert!(matches!(parse("  q!  "), Ok(Command::Quit { force: true })));
+        assert!(
+            matches!(parse("e! door.json"), Ok(Command::Edit { path, force: true }) if path == std::path::Path::new("door.json"))
+        );
+        assert!(
+            matches!(parse(r#"rename "Door open" Open"#), Ok(Command::Rename { from, to }) if from == "Door open" && to == "Open")
+        );
+        assert!(
+            matches!(parse("note mind the gap"), Ok(Command::Note(Some(text))) if text == "mind the gap")
+        );
+        assert!(matches!(parse("note"), Ok(Command::Note(None))));
+        assert!(matches!(parse("title"), Ok(Command::Title(text)) if text.is_empty()));
+        assert!(matches!(
+            parse("export door.svg legend"),
+            Ok(Command::Export { legend: true, .. })
+        ));
+        assert!(matches!(
+            parse("export door.svg"),
+            Ok(Command::Export { legend: false, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert_eq!(parse("").err().unwrap(), "empty command");
+        assert_eq!(parse(r#"new "Door"#).err().unwrap(), "unterminated quote");
+        assert!(parse("rename A").is_err());
+        assert!(parse("fly away").err().unwrap().ends_with("fly"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("S*", "S12"));
+        assert!(matches("S?", "S1"));
+        assert!(!matches("S?", "S12"));
+        assert!(matches("*open*", "Door open now"));
+        assert!(!matches("open", "Door open"));
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vector2d::Vector2D;
+
+// User settings read at startup. Every field is optional in the file, e.g.
+//
+//     {
+//         "x_bounds": [0, 2000],
+//         "y_bounds": [0, 2000],
+//         "move_step": 20,
+//         "edges": { "self_loop": { "arrowhead": "closed", "dashed": true } },
+//         "states": { "size": "fixed", "radius": 15, "label": "below" }
+//     }
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // The part of the canvas shown when the view is reset.
+    pub x_bounds: [f64; 2],
+    pub y_bounds: [f64; 2],
+    // Where new states are placed, or as close to it as there is room.
+    pub spawn: [f64; 2],
+    // How far the arrow keys move a state.
+    pub move_step: f64,
+    pub edges: EdgeStyles,
+    pub states: StateStyle,
+    // Whether selected states pulse so they are easy to spot.
+    pub pulse: bool,
+    // Language of the interface, like "de". Defaults to the one from the
+    // environment.
+    pub language: Option<String>,
+    // A profile file with key bindings and styles, which replace the
+    // defaults and the styles above. Relative to the config file.
+    pub profile: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeStyles {
+    pub normal: EdgeStyle,
+    // Transitions from a state back to itself.
+    pub self_loop: EdgeStyle,
+}
+
+impl Default for EdgeStyles {
+    fn default() -> Self {
+        Self {
+            normal: EdgeStyle::default(),
+            self_loop: EdgeStyle {
+                arrowhead_size: 4.0,
+                ..EdgeStyle::default()
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeStyle {
+    pub arrowhead: Arrowhead,
+    // Length of the arrowhead's sides, in canvas units.
+    pub arrowhead_size: f64,
+    pub dashed: bool,
+}
+
+impl Default for EdgeStyle {
+    fn default() -> Self {
+        Self {
+            arrowhead: Arrowhead::Open,
+            arrowhead_size: 10.0,
+            dashed: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Arrowhead {
+    Open,
+    Closed,
+    None,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateStyle {
+    pub size: StateSize,
+    // Radius of every circle when the size is fixed or the name is drawn
+    // outside, in canvas units.
+    pub radius: f64,
+    pub label: LabelPosition,
+}
+
+impl StateStyle {
+    pub fn validate(&self) -> std::io::Result<()> {
+        if !(self.radius.is_finite() && self.radius >= 5.0) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                t!("config.radius"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for StateStyle {
+    fn default() -> Self {
+        Self {
+            size: StateSize::Grow,
+            radius: 15.0,
+            label: LabelPosition::Inside,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StateSize {
+    // Circles grow to fit the whole name.
+    Grow,
+    // Circles all have the same radius, and names that don't fit are cut
+    // short with an ellipsis.
+    Fixed,
+}
+
+// Where state names are drawn. Names outside the circle are never cut short,
+// and the circles all get the fixed radius since they don't have to fit them.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelPosition {
+    Inside,
+    Below,
+    Beside,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            x_bounds: [0.0, 500.0],
+            y_bounds: [0.0, 500.0],
+            spawn: [200.0, 200.0],
+            move_step: 5.0,
+            edges: EdgeStyles::default(),
+            states: StateStyle::default(),
+            pulse: true,
+            language: None,
+            profile: None,
+        }
+    }
+}
+
+impl Config {
+    // `$XDG_CONFIG_HOME/fsmtui/config.json`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(base.join("fsmtui").join("config.json"))
+    }
+
+    // Only parses the file, so the language it names can be set up before
+    // `validate` reports anything.
+    pub fn read(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn validate(&self) -> std::io::Result<()> {
+        let valid = |[min, max]: [f64; 2]| min.is_finite() && max.is_finite() && min < max;
+        if !valid(self.x_bounds) || !valid(self.y_bounds) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                t!("config.bounds"),
+            ));
+        }
+        self.states.validate()
+    }
+
+    pub fn center(&self) -> Vector2D {
+        Vector2D {
+            x: (self.x_bounds[0] + self.x_bounds[1]) / 2.0,
+            y: (self.y_bounds[0] + self.y_bounds[1]) / 2.0,
+        }
+    }
+}
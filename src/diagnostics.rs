@@ -0,0 +1,258 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::analysis;
+use crate::properties::Property;
+use crate::{storage, FSMState};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    FailedProperty,
+    NoInitialState,
+    Unreachable,
+    DuplicateName,
+    Isolated,
+    Deadlock,
+    NoAcceptingStates,
+    CannotAccept,
+}
+
+impl Kind {
+    // The message for an issue of this kind about the state `name`, which
+    // general issues ignore. Failed properties are described where they are
+    // checked, with the reason.
+    fn message(self, name: &str) -> String {
+        match self {
+            Self::FailedProperty => unreachable!("failed properties carry a reason"),
+            Self::NoInitialState => t!("issue.no_initial"),
+            Self::Unreachable => t!("issue.unreachable", name = name),
+            Self::DuplicateName => t!("issue.duplicate", name = name),
+            Self::Isolated => t!("issue.isolated", name = name),
+            Self::Deadlock => t!("issue.deadlock", name = name),
+            Self::NoAcceptingStates => t!("issue.no_accepting"),
+            Self::CannotAccept => t!("issue.cannot_accept", name = name),
+        }
+    }
+
+    // The check `fsmtui check` asserts this kind of issue with, as in
+    // `--no-unreachable`, or `--properties` for declared properties.
+    fn flag(self) -> &'static str {
+        match self {
+            Self::FailedProperty => "--properties",
+            Self::NoInitialState | Self::Unreachable => "--no-unreachable",
+            Self::DuplicateName => "--no-duplicates",
+            Self::Isolated => "--no-isolated",
+            Self::Deadlock => "--no-deadlocks",
+            // A trap is a state no accepting state can be reached from.
+            Self::NoAcceptingStates | Self::CannotAccept => "--no-traps",
+        }
+    }
+}
+
+const KINDS: [Kind; 8] = [
+    Kind::FailedProperty,
+    Kind::NoInitialState,
+    Kind::Unreachable,
+    Kind::DuplicateName,
+    Kind::Isolated,
+    Kind::Deadlock,
+    Kind::NoAcceptingStates,
+    Kind::CannotAccept,
+];
+
+pub struct Issue {
+    pub message: String,
+    pub kind: Kind,
+    // The state to jump to, if the issue is about a particular one.
+    pub state: Weak<RefCell<FSMState>>,
+}
+
+impl Issue {
+    fn about(state: &Rc<RefCell<FSMState>>, kind: Kind) -> Self {
+        Self {
+            message: kind.message(&state.borrow().name),
+            kind,
+            state: Rc::downgrade(state),
+        }
+    }
+
+    fn general(kind: Kind) -> Self {
+        Self {
+            message: kind.message(""),
+            kind,
+            state: Weak::new(),
+        }
+    }
+}
+
+pub fn collect(
+    states: &[Rc<RefCell<FSMState>>],
+    initial: &Weak<RefCell<FSMState>>,
+    properties: &[Property],
+) -> Vec<Issue> {
+    let mut issues = vec![];
+
+    for property in properties {
+        if let Err(failure) = property.check(states) {
+            issues.push(Issue {
+                message: t!("issue.failed", property = property, reason = failure.reason),
+                kind: Kind::FailedProperty,
+                state: failure
+                    .state
+                    .as_ref()
+                    .map(Rc::downgrade)
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    if states.is_empty() {
+        return issues;
+    }
+
+    match initial.upgrade() {
+        Some(initial) => {
+            let depths = analysis::depths(&initial);
+            issues.extend(
+                states
+                    .iter()
+                    .filter(|s| !depths.contains_key(&Rc::as_ptr(s)))
+                    .map(|s| Issue::about(s, Kind::Unreachable)),
+            );
+        }
+        None => issues.push(Issue::general(Kind::NoInitialState)),
+    }
+
+    let mut names: HashMap<String, usize> = HashMap::new();
+    for state in states {
+        *names.entry(state.borrow().name.clone()).or_default() += 1;
+    }
+    issues.extend(
+        states
+            .iter()
+            .filter(|s| names[&s.borrow().name] > 1)
+            .map(|s| Issue::about(s, Kind::DuplicateName)),
+    );
+
+    issues.extend(
+        analysis::orphans(states)
+            .iter()
+            .map(|s| Issue::about(s, Kind::Isolated)),
+    );
+
+    let deadlocks = analysis::deadlocks(states);
+    issues.extend(deadlocks.iter().map(|s| Issue::about(s, Kind::Deadlock)));
+
+    if !states.iter().any(|s| s.borrow().accepting) {
+        issues.push(Issue::general(Kind::NoAcceptingStates));
+    } else {
+        issues.extend(
+            analysis::cannot_accept(states)
+                .iter()
+                .filter(|s| !deadlocks.iter().any(|d| Rc::ptr_eq(s, d)))
+                .map(|s| Issue::about(s, Kind::CannotAccept)),
+        );
+    }
+
+    issues
+}
+
+fn usage() -> ! {
+    eprintln!("{}", t!("cli.check_usage"));
+    std::process::exit(2);
+}
+
+// Entry point for `fsmtui check`, runs the diagnostics on a machine without
+// starting the TUI, so machine files can be checked in test suites. Every
+// issue is printed, but only the checks asked for with flags fail the run
+// with status 1.
+pub fn run_cli(args: &[String]) -> std::io::Result<()> {
+    let mut asserted = vec![];
+    let mut path = None;
+
+    for arg in args {
+        match arg.as_str() {
+            // Transitions carry no input symbols, so there is nothing these
+            // could be checked against.
+            "--deterministic" | "--complete" => {
+                eprintln!("fsmtui: {}", t!("cli.needs_symbols", flag = arg));
+                std::process::exit(2);
+            }
+            flag if flag.starts_with("--") => {
+                let kinds = KINDS.iter().filter(|k| k.flag() == flag);
+                let before = asserted.len();
+                asserted.extend(kinds);
+                if asserted.len() == before {
+                    usage();
+                }
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let Some(path) = path else { usage() };
+    let machine = storage::backend(path.as_ref()).and_then(|backend| backend.load());
+    let machine = match machine {
+        Ok(machine) => machine,
+        Err(e) => {
+            eprintln!("fsmtui: {path}: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut failed = false;
+    for issue in collect(&machine.states, &machine.initial, &machine.properties) {
+        let severity = match asserted.contains(&issue.kind) {
+            true => "error",
+            false => "warning",
+        };
+        failed |= asserted.contains(&issue.kind);
+        println!("{path}: {severity}: {}", issue.message);
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<Kind> {
+        let machine = crate::import::mermaid(text).unwrap();
+        collect(&machine.states, &machine.initial, &machine.properties)
+            .into_iter()
+            .map(|issue| issue.kind)
+            .collect()
+    }
+
+    #[test]
+    fn finds_issues() {
+        let found =
+            kinds("stateDiagram-v2\n[*] --> A\nA --> B\nB --> A\nA --> [*]\nC --> D\nstate E\n");
+        assert!(found.contains(&Kind::Unreachable));
+        assert!(found.contains(&Kind::Isolated));
+        assert!(found.contains(&Kind::Deadlock));
+        assert!(found.contains(&Kind::CannotAccept));
+        assert!(!found.contains(&Kind::NoAcceptingStates));
+        assert!(!found.contains(&Kind::NoInitialState));
+
+        let found = kinds("stateDiagram-v2\nA --> B\n");
+        assert!(found.contains(&Kind::NoInitialState));
+        assert!(found.contains(&Kind::NoAcceptingStates));
+    }
+
+    #[test]
+    fn every_kind_has_a_flag() {
+        let flagged = |flag| KINDS.into_iter().filter(move |k| k.flag() == flag);
+        assert!(flagged("--no-traps").eq([Kind::NoAcceptingStates, Kind::CannotAccept]));
+        assert!(flagged("--no-unreachable").eq([Kind::NoInitialState, Kind::Unreachable]));
+        assert!(flagged("--no-deadlocks").eq([Kind::Deadlock]));
+        assert_eq!(flagged("--no-cycles").count(), 0);
+        assert!(KINDS.iter().all(|k| k.flag().starts_with("--")));
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ratatui::crossterm::{
+    event::{DisableBracketedPaste, DisableMouseCapture},
+    execute,
+};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use crate::machine_file::Snapshot;
+
+// The machine as of the last edit, if it has unsaved changes, and the
+// file it came from. Kept outside the app so it can still be reached when the
+// app panics or the process is told to stop.
+static UNSAVED: Mutex<Option<(Arc<Snapshot>, Option<PathBuf>)>> = Mutex::new(None);
+
+pub fn remember(unsaved: Option<(Arc<Snapshot>, Option<PathBuf>)>) {
+    if let Ok(mut slot) = UNSAVED.lock() {
+        *slot = unsaved;
+    }
+}
+
+// Restores the terminal and writes unsaved changes out on a panic, SIGTERM,
+// SIGHUP or SIGINT, then tells where they went.
+pub fn install() -> std::io::Result<()> {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        hook(info);
+        rescue();
+    }));
+
+    let mut signals = Signals::new([SIGTERM, SIGHUP, SIGINT])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            restore_terminal();
+            rescue();
+            std::process::exit(128 + signal);
+        }
+    });
+
+    Ok(())
+}
+
+fn restore_terminal() {
+    let _ = execute!(
+        std::io::stdout(),
+        DisableMouseCapture,
+        DisableBracketedPaste
+    );
+    ratatui::restore();
+}
+
+// Next to the open file as `NAME.rescue.json`, or in the temporary directory
+// for machines that were never saved.
+fn rescue() {
+    // The lock may be held by the thread that panicked.
+    let Some((snapshot, file)) = UNSAVED.try_lock().ok().and_then(|mut u| u.take()) else {
+        return;
+    };
+
+    let path = rescue_path(file.as_deref());
+    match snapshot.save(&path) {
+        Ok(()) => eprintln!("fsmtui: unsaved changes were written to {}", path.display()),
+        Err(e) => eprintln!(
+            "fsmtui: could not save unsaved changes to {}: {e}",
+            path.display()
+        ),
+    }
+}
+
+fn rescue_path(file: Option<&Path>) -> PathBuf {
+    match file {
+        Some(file) => {
+            // `file://` locations are written to the path they name.
+            let text = file.to_string_lossy();
+            let path = text.strip_prefix("file://").map_or(file, Path::new);
+            path.with_extension("rescue.json")
+        }
+        None => std::env::temp_dir().join(format!("fsmtui-rescue-{}.json", std::process::id())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescues_next_to_the_file() {
+        assert_eq!(
+            rescue_path(Some(Path::new("/tmp/door.json"))),
+            Path::new("/tmp/door.rescue.json")
+        );
+        assert_eq!(
+            rescue_path(Some(Path::new("file:///tmp/door.json"))),
+            Path::new("/tmp/door.rescue.json")
+        );
+        assert!(rescue_path(None).starts_with(std::env::temp_dir()));
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event};
+
+use crate::writer::Written;
+
+pub enum AppEvent {
+    Input(Event),
+    Tick,
+    Written(Written),
+}
+
+// Terminal input is read on its own thread with a blocking `event::read()`
+// and funneled into one channel together with periodic ticks and finished
+// writes, so the main loop sleeps until there is something to do.
+pub struct Events {
+    tx: Sender<std::io::Result<AppEvent>>,
+    rx: Receiver<std::io::Result<AppEvent>>,
+}
+
+impl Events {
+    pub fn new(tick_rate: Duration) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        let input_tx = tx.clone();
+        thread::spawn(move || loop {
+            let event = event::read().map(AppEvent::Input);
+            let failed = event.is_err();
+
+            if input_tx.send(event).is_err() || failed {
+                break;
+            }
+        });
+
+        let tick_tx = tx.clone();
+        thread::spawn(move || Self::tick(tick_tx, tick_rate));
+
+        Self { tx, rx }
+    }
+
+    // For other threads to report back on.
+    pub fn sender(&self) -> Sender<std::io::Result<AppEvent>> {
+        self.tx.clone()
+    }
+
+    // Ticks are scheduled on a fixed clock instead of sleeping a whole period
+    // after each one, so they keep their rate however long sending takes.
+    fn tick(tx: Sender<std::io::Result<AppEvent>>, tick_rate: Duration) {
+        let mut next = Instant::now();
+        loop {
+            next += tick_rate;
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+
+            if tx.send(Ok(AppEvent::Tick)).is_err() {
+                break;
+            }
+        }
+    }
+
+    pub fn next(&self) -> std::io::Result<AppEvent> {
+        // A sender is kept here too, so the channel can't close while it is
+        // read from. Read errors arrive as events.
+        self.rx
+            .recv()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e))?
+    }
+}
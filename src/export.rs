@@ -0,0 +1,584 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::rc::{Rc, Weak};
+
+use crate::analysis;
+use crate::config::{Arrowhead, EdgeStyle, EdgeStyles, LabelPosition, StateStyle};
+use crate::machine_file::Metadata;
+use crate::{FSMState, Pseudo};
+
+// Diagrams of the machine for sharing, picked by file extension. Both the
+// web view and `:export` draw through here.
+pub enum Format {
+    Svg,
+    Dot,
+}
+
+impl Format {
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("svg") => Some(Self::Svg),
+            Some("dot" | "gv") => Some(Self::Dot),
+            _ => None,
+        }
+    }
+}
+
+// The kinds of marks a diagram can show, each explained by a legend entry.
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    State,
+    Transition,
+    SelfLoop,
+    Initial,
+    Accepting,
+    Choice,
+    Junction,
+    Isolated,
+    Note,
+}
+
+impl Mark {
+    fn text(self) -> String {
+        match self {
+            Self::State => t!("legend.state"),
+            Self::Transition => t!("legend.transition"),
+            Self::SelfLoop => t!("legend.self_loop"),
+            Self::Initial => t!("legend.initial"),
+            Self::Accepting => t!("legend.accepting"),
+            Self::Choice => t!("legend.choice"),
+            Self::Junction => t!("legend.junction"),
+            Self::Isolated => t!("legend.isolated"),
+            Self::Note => t!("legend.note"),
+        }
+    }
+}
+
+// The marks the machine uses, so the legend leaves out what doesn't appear.
+fn marks(states: &[Rc<RefCell<FSMState>>], initial: &Weak<RefCell<FSMState>>) -> Vec<Mark> {
+    let any = |f: fn(&FSMState) -> bool| states.iter().any(|s| f(&s.borrow()));
+    let mut marks = vec![Mark::State, Mark::Transition];
+    if states.iter().any(|s| {
+        let pointer = Rc::as_ptr(s);
+        s.borrow().next_states.iter().any(|n| n.as_ptr() == pointer)
+    }) {
+        marks.push(Mark::SelfLoop);
+    }
+    if initial.strong_count() > 0 {
+        marks.push(Mark::Initial);
+    }
+    if any(|s| s.accepting && s.pseudo.is_none()) {
+        marks.push(Mark::Accepting);
+    }
+    if any(|s| s.pseudo == Some(Pseudo::Choice)) {
+        marks.push(Mark::Choice);
+    }
+    if any(|s| s.pseudo == Some(Pseudo::Junction)) {
+        marks.push(Mark::Junction);
+    }
+    if !analysis::orphans(states).is_empty() {
+        marks.push(Mark::Isolated);
+    }
+    if any(|s| !s.notes.is_empty()) {
+        marks.push(Mark::Note);
+    }
+    marks
+}
+
+// An arrowhead as drawn on the canvas, `size` canvas units long, for
+// `marker-end` to refer to by `id`.
+fn marker(id: &str, style: &EdgeStyle) -> String {
+    let head = match style.arrowhead {
+        Arrowhead::Open => r##"<path d="M0,0 L10,5 L0,10" fill="none" stroke="#ddd"/>"##,
+        Arrowhead::Closed => r##"<path d="M0,0 L10,5 L0,10 z" fill="none" stroke="#ddd"/>"##,
+        Arrowhead::None => return String::new(),
+    };
+    format!(
+        r#"<marker id="{id}" viewBox="-1 -1 12 12" refX="10" refY="5" markerUnits="userSpaceOnUse" markerWidth="{size:.1}" markerHeight="{size:.1}" orient="auto">{head}</marker>"#,
+        size = style.arrowhead_size * 1.2
+    )
+}
+
+// The stroke of a line drawn in `style`, with the marker made for it by
+// `marker`.
+fn stroke(id: &str, style: &EdgeStyle) -> String {
+    let dash = match style.dashed {
+        true => r#" stroke-dasharray="6,4""#,
+        false => "",
+    };
+    let head = match style.arrowhead {
+        Arrowhead::None => String::new(),
+        _ => format!(r#" marker-end="url(#{id})""#),
+    };
+    format!(r##"stroke="#ddd"{dash}{head}"##)
+}
+
+// The path of a loop from a state at (`x`, `y`) in SVG coordinates back to
+// itself, as the canvas draws it: an arc over the top of the circle, from
+// where it leaves the right side to where it comes back in on the left.
+fn self_loop(x: f64, y: f64, radius: f64) -> String {
+    let loop_radius = radius * 0.8;
+    let offset = radius + loop_radius * 0.3;
+    let sin = (radius * radius - loop_radius * loop_radius - offset * offset)
+        / (2.0 * offset * loop_radius);
+    let start = sin.clamp(-1.0, 1.0).asin();
+    let point = |angle: f64| {
+        (
+            x + loop_radius * angle.cos(),
+            y - offset - loop_radius * angle.sin(),
+        )
+    };
+    let ((x1, y1), (x2, y2)) = (point(start), point(std::f64::consts::PI - start));
+    let large = u8::from(start < 0.0);
+    format!("M{x1:.1},{y1:.1} A{loop_radius:.1},{loop_radius:.1} 0 {large} 0 {x2:.1},{y2:.1}")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Same coordinate space as the canvas, showing its configured bounds, with y
+// flipped since SVG grows downwards.
+pub fn svg(
+    states: &[Rc<RefCell<FSMState>>],
+    initial: &Weak<RefCell<FSMState>>,
+    ([left, right], [bottom, top]): ([f64; 2], [f64; 2]),
+    style: &StateStyle,
+    edges: &EdgeStyles,
+    metadata: &Metadata,
+) -> String {
+    let mut svg = format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{left} 0 {} {}" width="100%" height="100%" font-family="monospace" font-size="8">
+<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="#ddd"/></marker>{}{}</defs>
+"##,
+        right - left,
+        top - bottom,
+        marker("transition", &edges.normal),
+        marker("self-loop", &edges.self_loop),
+    );
+    let (normal, looped) = (
+        stroke("transition", &edges.normal),
+        stroke("self-loop", &edges.self_loop),
+    );
+    if !metadata.title.is_empty() {
+        let _ = writeln!(svg, "<title>{}</title>", escape(&metadata.title));
+    }
+    // SVG has no author element, so the author goes into the description.
+    let description = match (metadata.author.as_str(), metadata.description.as_str()) {
+        ("", description) => description.to_string(),
+        (author, "") => t!("export.by", author = author),
+        (author, description) => t!(
+            "export.by_with_description",
+            author = author,
+            description = description
+        ),
+    };
+    if !description.is_empty() {
+        let _ = writeln!(svg, "<desc>{}</desc>", escape(&description));
+    }
+
+    let isolated: HashSet<_> = analysis::orphans(states).iter().map(Rc::as_ptr).collect();
+    for state_ref in states {
+        let state = state_ref.borrow();
+        let (x, y, r) = (state.x, top - state.y, state.circle_radius(style));
+        // Isolated states are outlined dashed, as on the canvas.
+        let dash = match isolated.contains(&Rc::as_ptr(state_ref)) {
+            true => r#" stroke-dasharray="3,2""#,
+            false => "",
+        };
+
+        for next in state.next_states.iter().filter_map(Weak::upgrade) {
+            // Shown as a tooltip on the transition.
+            let title = state.note(Rc::as_ptr(&next)).map_or(String::new(), |note| {
+                format!("<title>{}</title>", escape(note))
+            });
+            if Rc::ptr_eq(state_ref, &next) {
+                let _ = writeln!(
+                    svg,
+                    r#"<path d="{}" fill="none" {looped}>{title}</path>"#,
+                    self_loop(x, y, r)
+                );
+                continue;
+            }
+
+            let bend = state.bend(Rc::as_ptr(&next));
+            let next = next.borrow();
+            let (nx, ny) = (next.x, top - next.y);
+            let length = (nx - x).hypot(ny - y);
+            if length == 0.0 {
+                continue;
+            }
+
+            let nr = next.circle_radius(style);
+            if let Some(bend) = bend {
+                let (cx, cy) = ((x + nx) / 2.0 + bend.x, (y + ny) / 2.0 - bend.y);
+                let toward = |(fx, fy): (f64, f64), r: f64| {
+                    let length = (cx - fx).hypot(cy - fy).max(f64::EPSILON);
+                    (fx + (cx - fx) / length * r, fy + (cy - fy) / length * r)
+                };
+                let ((x1, y1), (x2, y2)) = (toward((x, y), r), toward((nx, ny), nr));
+                let _ = writeln!(
+                    svg,
+                    r#"<path d="M{x1:.1},{y1:.1} Q{cx:.1},{cy:.1} {x2:.1},{y2:.1}" fill="none" {normal}>{title}</path>"#
+                );
+                continue;
+            }
+
+            let (dx, dy) = ((nx - x) / length, (ny - y) / length);
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" {normal}>{title}</line>"#,
+                x + dx * r,
+                y + dy * r,
+                nx - dx * nr,
+                ny - dy * nr
+            );
+        }
+
+        match state.pseudo {
+            Some(pseudo) => {
+                let diamond = |r: f64| {
+                    format!(
+                        r##"<polygon points="{:.1},{y:.1} {x:.1},{:.1} {:.1},{y:.1} {x:.1},{:.1}" fill="none" stroke="#ddd"{dash}/>"##,
+                        x + r,
+                        y - r,
+                        x - r,
+                        y + r
+                    )
+                };
+                let _ = writeln!(svg, "{}", diamond(r));
+                if pseudo == Pseudo::Junction {
+                    let _ = writeln!(svg, "{}", diamond(r * 0.5));
+                }
+            }
+            None => {
+                let _ = writeln!(
+                    svg,
+                    r##"<circle cx="{x:.1}" cy="{y:.1}" r="{r:.1}" fill="none" stroke="#ddd"{dash}/>"##
+                );
+            }
+        }
+        if state.accepting && state.pseudo.is_none() {
+            let _ = writeln!(
+                svg,
+                r##"<circle cx="{x:.1}" cy="{y:.1}" r="{:.1}" fill="none" stroke="#ddd"/>"##,
+                r - 3.0
+            );
+        }
+        let (label_x, label_y, anchor) = match style.label {
+            LabelPosition::Inside => (x, y, "middle"),
+            LabelPosition::Below => (x, y + r + 8.0, "middle"),
+            LabelPosition::Beside => (x + r + 3.0, y, "start"),
+        };
+        let _ = writeln!(
+            svg,
+            r##"<text x="{label_x:.1}" y="{label_y:.1}" fill="#ddd" text-anchor="{anchor}" dominant-baseline="middle">{}</text>"##,
+            escape(&state.label(style))
+        );
+    }
+
+    if let Some(initial) = initial.upgrade() {
+        let initial = initial.borrow();
+        let (x, y) = (initial.x - initial.circle_radius(style), top - initial.y);
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{:.1}" y1="{y:.1}" x2="{x:.1}" y2="{y:.1}" stroke="#ddd" marker-end="url(#arrow)"/>"##,
+            x - 20.0
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Draws a legend from `svg_legend` on top of a diagram from `svg`.
+pub fn add_legend(svg: &str, legend: &str) -> String {
+    svg.replace("</svg>", &(legend.to_string() + "</svg>"))
+}
+
+// A box in the bottom left corner with a sample of each kind of mark the
+// machine uses, next to what it means.
+pub fn svg_legend(
+    states: &[Rc<RefCell<FSMState>>],
+    initial: &Weak<RefCell<FSMState>>,
+    ([left, right], [bottom, top]): ([f64; 2], [f64; 2]),
+) -> String {
+    const ROW: f64 = 12.0;
+
+    let entries = marks(states, initial);
+    let height = entries.len() as f64 * ROW + 6.0;
+    let width = ((right - left) / 2.0).min(150.0);
+    let (x0, y0) = (left + 4.0, top - bottom - height - 4.0);
+    let mut svg = format!(
+        r##"<g id="legend"><rect x="{x0:.1}" y="{y0:.1}" width="{width:.1}" height="{height:.1}" fill="#111" stroke="#666"/>
+"##
+    );
+
+    for (i, mark) in entries.iter().enumerate() {
+        let (x, y) = (x0 + 16.0, y0 + 3.0 + ROW * (i as f64 + 0.5));
+        let circle = |r: f64, dash: &str| {
+            format!(
+                r##"<circle cx="{x:.1}" cy="{y:.1}" r="{r:.1}" fill="none" stroke="#ddd"{dash}/>"##
+            )
+        };
+        let diamond = |r: f64| {
+            format!(
+                r##"<polygon points="{:.1},{y:.1} {x:.1},{:.1} {:.1},{y:.1} {x:.1},{:.1}" fill="none" stroke="#ddd"/>"##,
+                x + r,
+                y - r,
+                x - r,
+                y + r
+            )
+        };
+        let arrow = |from: f64, to: f64| {
+            format!(
+                r##"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="#ddd" marker-end="url(#arrow)"/>"##,
+                x + from,
+                x + to
+            )
+        };
+
+        let sample = match mark {
+            Mark::State => circle(5.0, ""),
+            Mark::Accepting => circle(5.0, "") + &circle(3.0, ""),
+            Mark::Choice => diamond(5.0),
+            Mark::Junction => diamond(5.0) + &diamond(2.5),
+            Mark::Initial => arrow(-14.0, -5.0) + &circle(5.0, ""),
+            Mark::Isolated => circle(5.0, r#" stroke-dasharray="3,2""#),
+            Mark::Transition | Mark::Note => arrow(-7.0, 7.0),
+            // Lowered so the loop above the circle fits the row.
+            Mark::SelfLoop => format!(
+                r##"<circle cx="{x:.1}" cy="{:.1}" r="3.0" fill="none" stroke="#ddd"/><path d="{}" fill="none" stroke="#ddd" marker-end="url(#arrow)"/>"##,
+                y + 2.5,
+                self_loop(x, y + 2.5, 3.0)
+            ),
+        };
+        let text = match mark {
+            Mark::Note => t!("legend.note_hover"),
+            mark => mark.text(),
+        };
+        let _ = writeln!(
+            svg,
+            r##"{sample}<text x="{:.1}" y="{y:.1}" fill="#ddd" dominant-baseline="middle">{}</text>"##,
+            x + 12.0,
+            escape(&text)
+        );
+    }
+
+    svg.push_str("</g>\n");
+    svg
+}
+
+// Graphviz keeps its own layout, so positions and bends are left out. Notes
+// become transition labels.
+pub fn dot(
+    states: &[Rc<RefCell<FSMState>>],
+    initial: &Weak<RefCell<FSMState>>,
+    metadata: &Metadata,
+    legend: bool,
+) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let ids: HashMap<*const RefCell<FSMState>, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (Rc::as_ptr(s), i))
+        .collect();
+    let isolated: HashSet<_> = analysis::orphans(states).iter().map(Rc::as_ptr).collect();
+
+    let mut dot = String::from("digraph {\n    node [shape=circle];\n");
+    if !metadata.title.is_empty() {
+        let _ = writeln!(dot, "    label={};", quote(&metadata.title));
+    }
+
+    for (i, state_ref) in states.iter().enumerate() {
+        let state = state_ref.borrow();
+        let mut attributes = vec![format!("label={}", quote(&state.name))];
+        match (state.pseudo, state.accepting) {
+            (Some(Pseudo::Choice), _) => attributes.push("shape=diamond".to_string()),
+            (Some(Pseudo::Junction), _) => {
+                attributes.push("shape=diamond, peripheries=2".to_string())
+            }
+            (None, true) => attributes.push("shape=doublecircle".to_string()),
+            (None, false) => {}
+        }
+        if isolated.contains(&Rc::as_ptr(state_ref)) {
+            attributes.push("style=dashed".to_string());
+        }
+        let _ = writeln!(dot, "    s{i} [{}];", attributes.join(", "));
+    }
+
+    if let Some(initial) = initial.upgrade() {
+        let _ = writeln!(
+            dot,
+            "    start [shape=point];\n    start -> s{};",
+            ids[&Rc::as_ptr(&initial)]
+        );
+    }
+
+    for (i, state) in states.iter().enumerate() {
+        let state = state.borrow();
+        for next in state.next_states.iter().filter_map(Weak::upgrade) {
+            let label = state
+                .note(Rc::as_ptr(&next))
+                .map_or(String::new(), |note| format!(" [label={}]", quote(note)));
+            let _ = writeln!(dot, "    s{i} -> s{}{label};", ids[&Rc::as_ptr(&next)]);
+        }
+    }
+
+    if legend {
+        let _ = writeln!(
+            dot,
+            "    subgraph cluster_legend {{\n        label={};",
+            quote(&t!("legend.title"))
+        );
+        for (i, mark) in marks(states, initial).iter().enumerate() {
+            let text = quote(&mark.text());
+            let _ = match mark {
+                Mark::State => writeln!(dot, "        legend{i} [label={text}];"),
+                Mark::Accepting => {
+                    writeln!(dot, "        legend{i} [label={text}, shape=doublecircle];")
+                }
+                Mark::Choice => writeln!(dot, "        legend{i} [label={text}, shape=diamond];"),
+                Mark::Junction => writeln!(
+                    dot,
+                    "        legend{i} [label={text}, shape=diamond, peripheries=2];"
+                ),
+                Mark::Isolated => {
+                    writeln!(dot, "        legend{i} [label={text}, style=dashed];")
+                }
+                Mark::Initial => writeln!(
+                    dot,
+                    "        legend{i} [label={text}];\n        legend{i}start [shape=point];\n        legend{i}start -> legend{i};"
+                ),
+                Mark::SelfLoop => writeln!(
+                    dot,
+                    "        legend{i} [label={text}, shape=plaintext];\n        legend{i} -> legend{i};"
+                ),
+                Mark::Transition | Mark::Note => writeln!(
+                    dot,
+                    "        legend{i}from [shape=point];\n        legend{i}to [shape=point];\n        legend{i}from -> legend{i}to [label={text}];"
+                ),
+            };
+        }
+        dot.push_str("    }\n");
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine_file::Machine;
+
+    const BOUNDS: ([f64; 2], [f64; 2]) = ([0.0, 500.0], [0.0, 500.0]);
+
+    fn machine(text: &str) -> Machine {
+        crate::import::mermaid(&format!("stateDiagram-v2\n{text}")).unwrap()
+    }
+
+    fn svg_of(machine: &Machine, edges: &EdgeStyles) -> String {
+        svg(
+            &machine.states,
+            &machine.initial,
+            BOUNDS,
+            &StateStyle::default(),
+            edges,
+            &machine.metadata,
+        )
+    }
+
+    #[test]
+    fn draws_self_loops() {
+        let machine = machine("[*] --> A\nA --> A\nA --> B\n");
+        let svg = svg_of(&machine, &EdgeStyles::default());
+
+        let loops: Vec<_> = svg.lines().filter(|l| l.contains(" A")).collect();
+        assert_eq!(loops.len(), 1);
+        assert!(loops[0].contains(r#"marker-end="url(#self-loop)""#));
+        assert_eq!(svg.matches(r#"marker-end="url(#transition)""#).count(), 1);
+        assert!(svg.contains(r#"<marker id="self-loop""#));
+    }
+
+    #[test]
+    fn styles_self_loops() {
+        let machine = machine("A --> A\nA --> B\n");
+        let mut edges = EdgeStyles::default();
+        edges.self_loop.dashed = true;
+        edges.self_loop.arrowhead = Arrowhead::None;
+        let svg = svg_of(&machine, &edges);
+
+        let path = svg.lines().find(|l| l.contains(" A")).unwrap();
+        assert!(path.contains("stroke-dasharray"));
+        assert!(!path.contains("marker-end"));
+        assert!(!svg.contains(r#"<marker id="self-loop""#));
+        let line = svg.lines().find(|l| l.starts_with("<line")).unwrap();
+        assert!(!line.contains("stroke-dasharray"));
+    }
+
+    #[test]
+    fn starts_and_ends_self_loops_on_the_circle() {
+        let path = self_loop(100.0, 200.0, 20.0);
+        let numbers: Vec<f64> = path
+            .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        let [x1, y1, .., x2, y2] = numbers[..] else {
+            panic!("no points in {path}");
+        };
+
+        for (x, y) in [(x1, y1), (x2, y2)] {
+            assert!(((x - 100.0).hypot(y - 200.0) - 20.0).abs() < 0.2);
+            assert!(y < 200.0);
+        }
+        assert!(x1 > 100.0 && x2 < 100.0);
+    }
+
+    #[test]
+    fn explains_only_the_marks_in_use() {
+        let plain = machine("A --> B\n");
+        let legend = svg_legend(&plain.states, &plain.initial, BOUNDS);
+        assert!(legend.contains("Transition<"));
+        assert!(!legend.contains("same state"));
+        assert!(!legend.contains("Initial state"));
+
+        let looped = machine("[*] --> A\nA --> A\nA --> [*]\n");
+        let legend = svg_legend(&looped.states, &looped.initial, BOUNDS);
+        assert!(legend.contains("Transition back to the same state"));
+        assert!(legend.contains("Initial state"));
+        assert!(legend.contains("Accepting state"));
+
+        let svg = add_legend(&svg_of(&looped, &EdgeStyles::default()), &legend);
+        assert!(svg.ends_with("</g>\n</svg>\n"));
+    }
+
+    #[test]
+    fn writes_dot() {
+        let mut machine = machine("[*] --> A\nA --> A\nA --> B\nB --> [*]\nstate C\n");
+        machine.metadata.title = "Say \"hi\"".to_string();
+        let dot = dot(&machine.states, &machine.initial, &machine.metadata, false);
+
+        assert!(dot.contains(r#"label="Say \"hi\"";"#));
+        assert!(dot.contains("start -> s0;"));
+        assert!(dot.contains("s0 -> s0;"));
+        assert!(dot.contains("s0 -> s1;"));
+        assert!(dot.contains(r#"s1 [label="B", shape=doublecircle];"#));
+        assert!(dot.contains(r#"s2 [label="C", style=dashed];"#));
+        assert!(!dot.contains("cluster_legend"));
+    }
+
+    #[test]
+    fn writes_the_dot_legend() {
+        let machine = machine("A --> A\n");
+        let dot = dot(&machine.states, &machine.initial, &machine.metadata, true);
+
+        assert!(dot.contains("subgraph cluster_legend"));
+        assert!(dot.contains(r#"label="Transition back to the same state", shape=plaintext"#));
+        assert!(!dot.contains("Initial state"));
+        assert!(dot.ends_with("    }\n}\n"));
+    }
+}
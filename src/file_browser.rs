@@ -0,0 +1,290 @@
+use std::path::{Path, PathBuf};
+
+use ratatui::{
+    crossterm::event::{KeyCode, KeyEvent},
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    widgets::{Block, Clear, List, ListState, Paragraph},
+    Frame,
+};
+
+use crate::project::{self, Project};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Open,
+    SaveAs,
+}
+
+pub enum Outcome {
+    Pending,
+    Cancelled,
+    Chosen(PathBuf),
+}
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    // What a machine in a project holds.
+    detail: Option<String>,
+}
+
+// Overlay for picking a file to open or save to. The list shows the current
+// directory, while the input line accepts a path relative to it, with Tab
+// completing from the listed entries. Projects are browsed like directories
+// of machines, where the input line names a machine instead.
+pub struct FileBrowser {
+    pub mode: Mode,
+    dir: PathBuf,
+    project: Option<PathBuf>,
+    entries: Vec<Entry>,
+    selected: usize,
+    input: String,
+    error: Option<String>,
+}
+
+impl FileBrowser {
+    pub fn new(mode: Mode, dir: PathBuf) -> Self {
+        let mut browser = Self {
+            mode,
+            dir,
+            project: None,
+            entries: vec![],
+            selected: 0,
+            input: String::new(),
+            error: None,
+        };
+
+        browser.read_dir();
+        browser
+    }
+
+    // Starts out listing the machines in a project.
+    pub fn in_project(mode: Mode, path: PathBuf) -> Self {
+        let dir = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        let mut browser = Self::new(mode, dir);
+        browser.enter_project(path);
+        browser
+    }
+
+    fn enter_project(&mut self, path: PathBuf) {
+        self.entries.clear();
+        self.selected = 0;
+        self.input.clear();
+        self.error = None;
+
+        // Saving may create the project, so a missing one is just empty.
+        let machines = match (Project::open(&path, false), self.mode) {
+            (Err(e), Mode::SaveAs) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            (project, _) => project.and_then(|p| p.machines()),
+        };
+        match machines {
+            Ok(machines) => {
+                self.entries = machines
+                    .into_iter()
+                    .map(|m| {
+                        let counts = t!(
+                            "panel.project.machine",
+                            states = m.states,
+                            snapshots = m.snapshots
+                        );
+                        Entry {
+                            detail: Some(match m.title.is_empty() {
+                                true => counts,
+                                false => format!("{}, {counts}", m.title),
+                            }),
+                            name: m.name,
+                            is_dir: false,
+                        }
+                    })
+                    .collect()
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+        self.project = Some(path);
+    }
+
+    fn leave_project(&mut self) {
+        self.project = None;
+        self.input.clear();
+        self.read_dir();
+    }
+
+    fn read_dir(&mut self) {
+        self.entries.clear();
+        self.selected = 0;
+        self.error = None;
+
+        if self.dir.parent().is_some() {
+            self.entries.push(Entry {
+                name: "..".to_string(),
+                is_dir: true,
+                detail: None,
+            });
+        }
+
+        match std::fs::read_dir(&self.dir) {
+            Ok(read_dir) => {
+                let mut entries: Vec<Entry> = read_dir
+                    .filter_map(Result::ok)
+                    .map(|e| Entry {
+                        name: e.file_name().to_string_lossy().into_owned(),
+                        is_dir: e.path().is_dir(),
+                        detail: None,
+                    })
+                    .filter(|e| !e.name.starts_with('.'))
+                    .collect();
+
+                entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then(a.name.cmp(&b.name)));
+                self.entries.extend(entries);
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    fn enter(&mut self, path: &Path) -> Outcome {
+        if let Some(project) = &self.project {
+            let name = path.to_string_lossy();
+            return Outcome::Chosen(project::location(project, &name));
+        }
+
+        let path = self.dir.join(path);
+
+        if project::is_project(&path) {
+            self.enter_project(path);
+            Outcome::Pending
+        } else if path.is_dir() {
+            // Canonicalizing folds away the ".." components.
+            self.dir = path.canonicalize().unwrap_or(path);
+            self.input.clear();
+            self.read_dir();
+            Outcome::Pending
+        } else {
+            Outcome::Chosen(path)
+        }
+    }
+
+    fn complete(&mut self) {
+        let candidates: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|e| e.name.starts_with(&self.input))
+            .collect();
+
+        let Some(first) = candidates.first() else {
+            return;
+        };
+
+        let mut common = first.name.clone();
+        for candidate in &candidates[1..] {
+            let length = common
+                .char_indices()
+                .zip(candidate.name.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8());
+            common.truncate(length);
+        }
+
+        if candidates.len() == 1 && first.is_dir {
+            let name = first.name.clone();
+            self.enter(Path::new(&name));
+        } else {
+            self.input = common;
+        }
+    }
+
+    pub fn paste(&mut self, text: &str) {
+        self.input.push_str(text.lines().next().unwrap_or_default());
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Outcome {
+        self.error = None;
+
+        match key.code {
+            KeyCode::Esc => return Outcome::Cancelled,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1))
+            }
+            KeyCode::Tab => self.complete(),
+            KeyCode::Backspace if self.input.is_empty() && self.project.is_some() => {
+                self.leave_project()
+            }
+            KeyCode::Backspace if self.input.is_empty() => return self.enter(Path::new("..")),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(ch) => self.input.push(ch),
+            KeyCode::Enter => {
+                if !self.input.is_empty() {
+                    let input = PathBuf::from(&self.input);
+                    return self.enter(&input);
+                }
+
+                if let Some(entry) = self.entries.get(self.selected) {
+                    let name = PathBuf::from(&entry.name);
+                    return self.enter(&name);
+                }
+            }
+            _ => {}
+        }
+
+        Outcome::Pending
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let title = match self.mode {
+            Mode::Open => t!("panel.browser.open"),
+            Mode::SaveAs => t!("panel.browser.save_as"),
+        };
+
+        let [_, area, _] = Layout::horizontal([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .areas(area);
+        let [_, area, _] = Layout::vertical([
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
+        ])
+        .areas(area);
+
+        frame.render_widget(Clear, area);
+
+        let location = match &self.project {
+            Some(project) => t!("panel.project.title", path = project.display()),
+            None => self.dir.display().to_string(),
+        };
+        let block = Block::bordered().title(format!("{title}: {location}"));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [list_area, input_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(inner);
+
+        let list = List::new(self.entries.iter().map(|e| match &e.detail {
+            _ if e.is_dir => format!("{}/", e.name),
+            Some(detail) => format!("{}  {detail}", e.name),
+            None => e.name.clone(),
+        }))
+        .highlight_style(Style::new().reversed());
+
+        frame.render_stateful_widget(
+            list,
+            list_area,
+            &mut ListState::default().with_selected(Some(self.selected)),
+        );
+
+        let input = match &self.error {
+            Some(error) => Paragraph::new(error.clone().red()),
+            None => Paragraph::new(format!("> {}", self.input)),
+        };
+        frame.render_widget(input, input_area);
+    }
+}
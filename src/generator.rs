@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::machine_file::{self, Machine, Metadata};
+use crate::vector2d::Vector2D;
+use crate::FSMState;
+
+// xorshift64*, plenty for scattering states around; not meant to be secure.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn from_time() -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        // The state must never be zero.
+        Self(seed | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Uniform in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Builds `count` states scattered over the canvas, where every ordered pair of
+// distinct states is connected with probability `density`. The first state is
+// made initial.
+pub fn random_machine(count: usize, density: f64, rng: &mut Rng) -> Machine {
+    let states: Vec<_> = (0..count)
+        .map(|i| {
+            Rc::new(RefCell::new(FSMState::new(
+                format!("S{i}"),
+                20.0 + rng.next_f64() * 460.0,
+                20.0 + rng.next_f64() * 460.0,
+            )))
+        })
+        .collect();
+
+    for from in &states {
+        for to in &states {
+            if !Rc::ptr_eq(from, to) && rng.next_f64() < density {
+                from.borrow_mut().next_states.push(Rc::downgrade(to));
+            }
+        }
+    }
+
+    let initial = states.first().map(Rc::downgrade).unwrap_or_default();
+    Machine {
+        states,
+        initial,
+        properties: vec![],
+        metadata: Metadata::default(),
+    }
+}
+
+// A modulo-N counter: states `0` to `N-1` on a circle, each stepping to the
+// next and the last wrapping around to `0`, which is initial and accepting.
+pub fn counter(count: usize) -> Machine {
+    let center = Vector2D { x: 250.0, y: 250.0 };
+    let states: Vec<_> = (0..count)
+        .map(|i| {
+            let angle = std::f64::consts::TAU * i as f64 / count as f64;
+            let position = center + (Vector2D { x: 0.0, y: 200.0 }).rotate(-angle);
+            Rc::new(RefCell::new(FSMState::new(
+                i.to_string(),
+                position.x,
+                position.y,
+            )))
+        })
+        .collect();
+
+    for (i, state) in states.iter().enumerate() {
+        let next = &states[(i + 1) % count];
+        state.borrow_mut().next_states.push(Rc::downgrade(next));
+    }
+
+    let initial = states.first().map(Rc::downgrade).unwrap_or_default();
+    if let Some(first) = states.first() {
+        first.borrow_mut().accepting = true;
+    }
+    Machine {
+        states,
+        initial,
+        properties: vec![],
+        metadata: Metadata {
+            title: t!("generator.counter_title", count = count),
+            ..Metadata::default()
+        },
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", t!("cli.gen_usage"));
+    std::process::exit(2);
+}
+
+// Entry point for `fsmtui gen`, writes a random machine or one built from a
+// template without starting the TUI.
+pub fn run_cli(args: &[String]) -> std::io::Result<()> {
+    let mut template = "random".to_string();
+    let mut count = 100;
+    let mut density = 0.05;
+    let mut out = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--template" => {
+                template = args
+                    .next()
+                    .filter(|t| ["random", "counter"].contains(&t.as_str()))
+                    .cloned()
+                    .unwrap_or_else(|| usage())
+            }
+            "--states" => {
+                count = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--density" => {
+                density = args
+                    .next()
+                    .and_then(|d| d.parse().ok())
+                    .filter(|d| (0.0..=1.0).contains(d))
+                    .unwrap_or_else(|| usage())
+            }
+            path if out.is_none() && !path.starts_with("--") => out = Some(path),
+            _ => usage(),
+        }
+    }
+
+    let Some(out) = out else { usage() };
+
+    let machine = match template.as_str() {
+        "counter" if count > 0 => counter(count),
+        "counter" => usage(),
+        _ => random_machine(count, density, &mut Rng::from_time()),
+    };
+    machine_file::save(out, &machine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_counters() {
+        let machine = counter(5);
+        assert_eq!(machine.metadata.title, "Modulo-5 counter");
+        assert_eq!(machine.states.len(), 5);
+        assert!(machine
+            .initial
+            .upgrade()
+            .is_some_and(|s| Rc::ptr_eq(&s, &machine.states[0])));
+
+        for (i, state) in machine.states.iter().enumerate() {
+            let state = state.borrow();
+            assert_eq!(state.name, i.to_string());
+            assert_eq!(state.accepting, i == 0);
+            assert_eq!(state.next_states.len(), 1);
+            let next = state.next_states[0].upgrade().unwrap();
+            assert!(Rc::ptr_eq(&next, &machine.states[(i + 1) % 5]));
+        }
+    }
+
+    #[test]
+    fn builds_an_empty_counter() {
+        let machine = counter(0);
+        assert!(machine.states.is_empty());
+        assert!(machine.initial.upgrade().is_none());
+    }
+}
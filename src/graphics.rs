@@ -0,0 +1,186 @@
+use std::io::Write;
+
+use base64::Engine;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use ratatui::{
+    crossterm::{cursor, queue},
+    layout::Rect,
+    style::Color,
+    text::Line as TextLine,
+    widgets::canvas::Context,
+};
+
+use crate::surface::Surface;
+use crate::vector2d::Vector2D;
+
+// Every frame replaces the same image, so old frames never pile up.
+const IMAGE_ID: u32 = 1;
+
+// Kitty graphics are understood by kitty itself and by terminals that adopted
+// its protocol. There's no reliable way to ask, so this goes by what the
+// terminal says about itself.
+pub fn supported() -> bool {
+    let var = |name| std::env::var(name).unwrap_or_default();
+
+    std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || var("TERM").contains("kitty")
+        || matches!(var("TERM_PROGRAM").as_str(), "WezTerm" | "ghostty")
+}
+
+// Size of a terminal cell in pixels, if the terminal reports it.
+pub fn cell_size() -> Option<(u32, u32)> {
+    let size = ratatui::crossterm::terminal::window_size().ok()?;
+    if size.width == 0 || size.height == 0 || size.columns == 0 || size.rows == 0 {
+        return None;
+    }
+
+    Some((
+        (size.width / size.columns) as u32,
+        (size.height / size.rows) as u32,
+    ))
+}
+
+// Passes only the labels through to the terminal canvas, since the shapes are
+// drawn by the raster underneath.
+pub struct LabelsOnly<'a, 'b>(pub &'a mut Context<'b>);
+
+impl Surface for LabelsOnly<'_, '_> {
+    fn line(&mut self, _: Vector2D, _: Vector2D, _: Color) {}
+
+    fn circle(&mut self, _: Vector2D, _: f64, _: Color) {}
+
+    fn print(&mut self, x: f64, y: f64, text: TextLine<'static>) {
+        self.0.print(x, y, text);
+    }
+}
+
+// RGBA image covering the visible part of the canvas. Shapes are drawn by
+// stamping soft dots along them, which gives antialiased strokes without a
+// real rasterizer.
+pub struct Raster {
+    width: u32,
+    height: u32,
+    x_bounds: [f64; 2],
+    y_bounds: [f64; 2],
+    pixels: Vec<u8>,
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32, x_bounds: [f64; 2], y_bounds: [f64; 2]) -> Self {
+        Self {
+            width,
+            height,
+            x_bounds,
+            y_bounds,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    fn to_pixels(&self, point: Vector2D) -> (f64, f64) {
+        let [x0, x1] = self.x_bounds;
+        let [y0, y1] = self.y_bounds;
+        (
+            (point.x - x0) / (x1 - x0) * self.width as f64,
+            (y1 - point.y) / (y1 - y0) * self.height as f64,
+        )
+    }
+
+    fn stamp(&mut self, (x, y): (f64, f64), rgb: [u8; 3]) {
+        let (px, py) = (x.floor() as i64, y.floor() as i64);
+
+        for py in py - 1..=py + 1 {
+            for px in px - 1..=px + 1 {
+                if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                    continue;
+                }
+
+                let distance = (px as f64 + 0.5 - x).hypot(py as f64 + 0.5 - y);
+                let alpha = ((1.2 - distance).clamp(0.0, 1.0) * 255.0) as u8;
+                let i = ((py as u32 * self.width + px as u32) * 4) as usize;
+
+                if alpha > self.pixels[i + 3] {
+                    self.pixels[i..i + 3].copy_from_slice(&rgb);
+                    self.pixels[i + 3] = alpha;
+                }
+            }
+        }
+    }
+
+    // Sends the image to the terminal, placed at the top left cell of `area`
+    // and behind the text so labels stay readable.
+    pub fn show(&self, out: &mut impl Write, area: Rect) -> std::io::Result<()> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::fast());
+        encoder.write_all(&self.pixels)?;
+        let payload = base64::engine::general_purpose::STANDARD.encode(encoder.finish()?);
+
+        queue!(out, cursor::SavePosition, cursor::MoveTo(area.x, area.y))?;
+        clear(out)?;
+
+        let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let more = u8::from(i + 1 < chunks.len());
+            if i == 0 {
+                write!(
+                    out,
+                    "\x1b_Ga=T,f=32,o=z,s={},v={},i={IMAGE_ID},q=2,C=1,z=-1,m={more};",
+                    self.width, self.height
+                )?;
+            } else {
+                write!(out, "\x1b_Gm={more};")?;
+            }
+            out.write_all(chunk)?;
+            write!(out, "\x1b\\")?;
+        }
+
+        queue!(out, cursor::RestorePosition)?;
+        out.flush()
+    }
+}
+
+pub fn clear(out: &mut impl Write) -> std::io::Result<()> {
+    write!(out, "\x1b_Ga=d,d=I,i={IMAGE_ID},q=2\x1b\\")
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    match color {
+        Color::Red => [230, 70, 70],
+        Color::Green => [90, 210, 90],
+        Color::Yellow => [235, 210, 60],
+        Color::Cyan => [70, 200, 220],
+        Color::Magenta => [210, 90, 210],
+        Color::Gray => [150, 150, 150],
+        Color::Rgb(r, g, b) => [r, g, b],
+        _ => [225, 225, 225],
+    }
+}
+
+impl Surface for Raster {
+    fn line(&mut self, from: Vector2D, to: Vector2D, color: Color) {
+        let (from, to) = (self.to_pixels(from), self.to_pixels(to));
+        let steps = ((to.0 - from.0).hypot(to.1 - from.1) * 2.0).ceil().max(1.0) as usize;
+
+        for step in 0..=steps {
+            let t = step as f64 / steps as f64;
+            let point = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+            self.stamp(point, rgb(color));
+        }
+    }
+
+    fn circle(&mut self, center: Vector2D, radius: f64, color: Color) {
+        let (rx, ry) = (
+            radius / (self.x_bounds[1] - self.x_bounds[0]) * self.width as f64,
+            radius / (self.y_bounds[1] - self.y_bounds[0]) * self.height as f64,
+        );
+        let steps = (std::f64::consts::TAU * rx.max(ry) * 2.0).ceil().max(8.0) as usize;
+        let (cx, cy) = self.to_pixels(center);
+
+        for step in 0..steps {
+            let angle = std::f64::consts::TAU * step as f64 / steps as f64;
+            let point = (cx + rx * angle.cos(), cy + ry * angle.sin());
+            self.stamp(point, rgb(color));
+        }
+    }
+
+    fn print(&mut self, _: f64, _: f64, _: TextLine<'static>) {}
+}
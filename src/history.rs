@@ -0,0 +1,238 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::machine_file::Snapshot;
+
+// Every version of the machine in this session, as a tree: undoing and then
+// editing starts a new branch instead of throwing the undone edits away.
+pub struct History {
+    nodes: Vec<Node>,
+    current: usize,
+}
+
+struct Node {
+    parent: Option<usize>,
+    label: String,
+    time: Instant,
+    // Shared with the crash handler, which keeps the latest one.
+    snapshot: Arc<Snapshot>,
+    // The child redo goes to, which is the one most recently left by undo or
+    // created.
+    redo: Option<usize>,
+}
+
+// An entry of the history panel.
+pub struct Entry {
+    pub id: usize,
+    pub label: String,
+    pub age: Duration,
+    // How many branches deep the entry is, for indenting.
+    pub depth: usize,
+    pub current: bool,
+}
+
+impl History {
+    pub fn new(label: &str, snapshot: Snapshot) -> Self {
+        Self {
+            nodes: vec![Node {
+                parent: None,
+                label: label.to_string(),
+                time: Instant::now(),
+                snapshot: Arc::new(snapshot),
+                redo: None,
+            }],
+            current: 0,
+        }
+    }
+
+    // Adds a version of the machine, if it differs from the current one.
+    // Repeating the same edit, like moving a state step by step, updates the
+    // last entry instead of adding one per step. Returns whether anything
+    // changed.
+    pub fn record(&mut self, snapshot: Arc<Snapshot>) -> bool {
+        let current = &self.nodes[self.current];
+        if current.snapshot == snapshot {
+            return false;
+        }
+
+        let changes = current.snapshot.changes(&snapshot);
+        let label = match changes.as_slice() {
+            [] => t!("change.edited"),
+            [change] => change.clone(),
+            [change, rest @ ..] => t!("change.more", change = change, count = rest.len()),
+        };
+
+        let is_leaf = !self.nodes.iter().any(|n| n.parent == Some(self.current));
+        let current = &mut self.nodes[self.current];
+        if is_leaf && current.parent.is_some() && current.label == label {
+            current.snapshot = snapshot;
+            current.time = Instant::now();
+            return true;
+        }
+
+        self.nodes.push(Node {
+            parent: Some(self.current),
+            label,
+            time: Instant::now(),
+            snapshot,
+            redo: None,
+        });
+        let id = self.nodes.len() - 1;
+        self.nodes[self.current].redo = Some(id);
+        self.current = id;
+        true
+    }
+
+    pub fn undo(&mut self) -> Option<&Snapshot> {
+        let parent = self.nodes[self.current].parent?;
+        self.nodes[parent].redo = Some(self.current);
+        self.current = parent;
+        Some(&self.nodes[parent].snapshot)
+    }
+
+    pub fn redo(&mut self) -> Option<&Snapshot> {
+        self.current = self.nodes[self.current].redo?;
+        Some(&self.nodes[self.current].snapshot)
+    }
+
+    // Makes `id` current, pointing redo along the way there so redo can
+    // replay the jump step by step after undoing.
+    pub fn jump(&mut self, id: usize) -> Option<&Snapshot> {
+        self.nodes.get(id)?;
+
+        let mut child = id;
+        while let Some(parent) = self.nodes[child].parent {
+            self.nodes[parent].redo = Some(child);
+            child = parent;
+        }
+
+        self.current = id;
+        Some(&self.nodes[id].snapshot)
+    }
+
+    pub fn current_label(&self) -> &str {
+        &self.nodes[self.current].label
+    }
+
+    // Entries oldest first, depth first so each branch is listed in one
+    // piece under the entry it branched from.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = vec![];
+        let mut stack = vec![(0, 0)];
+
+        while let Some((id, depth)) = stack.pop() {
+            let node = &self.nodes[id];
+            entries.push(Entry {
+                id,
+                label: node.label.clone(),
+                age: node.time.elapsed(),
+                depth,
+                current: id == self.current,
+            });
+
+            // The first child continues the branch, later ones start new
+            // branches. Pushed in reverse so the first child is listed
+            // right after its parent.
+            let children: Vec<usize> = (0..self.nodes.len())
+                .filter(|&i| self.nodes[i].parent == Some(id))
+                .collect();
+            for (i, &child) in children.iter().enumerate().rev() {
+                stack.push((child, depth + usize::from(i > 0)));
+            }
+        }
+
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(states: &str) -> Arc<Snapshot> {
+        let text: String = states.split(' ').map(|s| format!("state {s}\n")).collect();
+        let machine = crate::import::mermaid(&format!("stateDiagram-v2\n{text}")).unwrap();
+        Arc::new(Snapshot::take(&machine))
+    }
+
+    fn history() -> History {
+        History::new("start", Snapshot::clone(&snapshot("A")))
+    }
+
+    fn layout(history: &History) -> Vec<(usize, usize, bool)> {
+        let entries = history.entries();
+        entries.iter().map(|e| (e.id, e.depth, e.current)).collect()
+    }
+
+    #[test]
+    fn undoes_and_redoes() {
+        let mut history = history();
+        assert!(history.undo().is_none());
+        assert!(history.record(snapshot("A B")));
+        assert!(!history.record(snapshot("A B")));
+        assert!(history.record(snapshot("A B C")));
+
+        assert!(history.undo() == Some(&*snapshot("A B")));
+        assert!(history.undo() == Some(&*snapshot("A")));
+        assert!(history.redo() == Some(&*snapshot("A B")));
+        assert!(history.redo() == Some(&*snapshot("A B C")));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn branches_when_editing_after_undo() {
+        let mut history = history();
+        history.record(snapshot("A B"));
+        history.record(snapshot("A B C"));
+        history.undo();
+        history.record(snapshot("A B D"));
+
+        // Nothing is thrown away: C is still there, on its own branch.
+        assert_eq!(
+            layout(&history),
+            vec![(0, 0, false), (1, 0, false), (2, 0, false), (3, 1, true)]
+        );
+
+        // Redo follows the branch that was created last.
+        history.undo();
+        assert!(history.redo() == Some(&*snapshot("A B D")));
+    }
+
+    #[test]
+    fn jumps_across_branches() {
+        let mut history = history();
+        history.record(snapshot("A B"));
+        history.record(snapshot("A B C"));
+        history.undo();
+        history.undo();
+        history.record(snapshot("A X"));
+
+        assert!(history.jump(2) == Some(&*snapshot("A B C")));
+        assert!(history.jump(99).is_none());
+        assert_eq!(
+            layout(&history),
+            vec![(0, 0, false), (1, 0, false), (2, 0, true), (3, 1, false)]
+        );
+
+        // Undoing back to the start and redoing retraces the jump.
+        history.undo();
+        history.undo();
+        assert!(history.redo() == Some(&*snapshot("A B")));
+        assert!(history.redo() == Some(&*snapshot("A B C")));
+    }
+
+    #[test]
+    fn lists_entries_oldest_first() {
+        let mut history = history();
+        history.record(snapshot("A B"));
+        history.undo();
+        history.record(snapshot("A C"));
+        history.record(snapshot("A C D"));
+
+        assert_eq!(history.entries()[0].label, "start");
+        assert_eq!(
+            layout(&history),
+            vec![(0, 0, false), (1, 0, false), (2, 1, false), (3, 1, true)]
+        );
+    }
+}
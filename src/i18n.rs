@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// Interface text is looked up by key in a locale file, a flat JSON object
+// like `{ "status.nothing_to_undo": "Nothing to undo." }`. Values may refer
+// to arguments as `{name}`. Keys missing from a translation fall back to
+// English, so partial translations work.
+const ENGLISH: &str = include_str!("../locales/en.json");
+
+// Translations that ship with the binary. More can be dropped into
+// `locales` in the config directory, as `<language>.json`.
+const BUILT_IN: [(&str, &str); 1] = [("de", include_str!("../locales/de.json"))];
+
+static STRINGS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+// Looks up the text for a key, filling in `name = value` arguments.
+macro_rules! t {
+    ($key:literal) => {
+        $crate::i18n::text($key, &[])
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::text(
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+        )
+    };
+}
+
+// The language from `LC_ALL`, `LC_MESSAGES` or `LANG`, e.g. `de` for
+// `de_DE.UTF-8`.
+pub fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .split(['_', '.', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .filter(|language| !language.is_empty() && language != "C" && language != "POSIX")
+}
+
+// Loads the strings for `language`, on top of English. Should be called once
+// before the interface is drawn; later calls are ignored.
+pub fn init(language: Option<&str>, locale_dir: Option<PathBuf>) -> Result<(), String> {
+    let mut strings = parse(ENGLISH).expect("the English locale is valid");
+    let mut result = Ok(());
+
+    if let Some(language) = language.filter(|l| *l != "en") {
+        let file = locale_dir.map(|dir| dir.join(format!("{language}.json")));
+        let translation = match file.filter(|f| f.exists()) {
+            Some(file) => std::fs::read_to_string(&file)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse(&text))
+                .map_err(|e| format!("{}: {e}", file.display())),
+            None => match BUILT_IN.iter().find(|(l, _)| *l == language) {
+                Some((_, text)) => parse(text),
+                // Unknown languages quietly stay in English.
+                None => Ok(HashMap::new()),
+            },
+        };
+
+        match translation {
+            Ok(translation) => strings.extend(translation),
+            Err(e) => result = Err(e),
+        }
+    }
+
+    let _ = STRINGS.set(strings);
+    result
+}
+
+fn parse(text: &str) -> Result<HashMap<String, String>, String> {
+    serde_json::from_str(text).map_err(|e| e.to_string())
+}
+
+pub fn text(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let strings = STRINGS.get_or_init(|| parse(ENGLISH).unwrap_or_default());
+    let Some(template) = strings.get(key) else {
+        return key.to_string();
+    };
+
+    let mut text = template.clone();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
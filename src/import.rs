@@ -0,0 +1,459 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::{Rc, Weak};
+
+use crate::machine_file::{Machine, Metadata};
+use crate::vector2d::Vector2D;
+use crate::{FSMState, Pseudo};
+
+// Collects states by identifier while a diagram is parsed. Transitions in
+// text formats carry labels, but since connections here are unlabeled the
+// labels are dropped and parallel edges collapse into one.
+struct Builder {
+    states: Vec<Rc<RefCell<FSMState>>>,
+    ids: HashMap<String, usize>,
+    initial: Weak<RefCell<FSMState>>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            states: vec![],
+            ids: HashMap::new(),
+            initial: Weak::new(),
+        }
+    }
+
+    fn state(&mut self, id: &str) -> Rc<RefCell<FSMState>> {
+        let index = *self.ids.entry(id.to_string()).or_insert_with(|| {
+            self.states.push(Rc::new(RefCell::new(FSMState::new(
+                id.to_string(),
+                0.0,
+                0.0,
+            ))));
+            self.states.len() - 1
+        });
+
+        self.states[index].clone()
+    }
+
+    fn rename(&mut self, id: &str, name: &str) {
+        self.state(id).borrow_mut().name = name.to_string();
+    }
+
+    fn connect(&mut self, from: &str, to: &str) {
+        let to = self.state(to);
+        let from = self.state(from);
+        let mut from = from.borrow_mut();
+
+        if !from
+            .next_states
+            .iter()
+            .any(|n| std::ptr::eq(n.as_ptr(), Rc::as_ptr(&to)))
+        {
+            from.next_states.push(Rc::downgrade(&to));
+        }
+    }
+
+    fn mark_initial(&mut self, id: &str) {
+        if self.initial.upgrade().is_none() {
+            self.initial = Rc::downgrade(&self.state(id));
+        }
+    }
+
+    fn mark_accepting(&mut self, id: &str) {
+        self.state(id).borrow_mut().accepting = true;
+    }
+
+    // Text formats carry no positions, so states are spread on a circle.
+    fn finish(self) -> Machine {
+        let center = Vector2D { x: 250.0, y: 250.0 };
+        let count = self.states.len().max(1) as f64;
+
+        for (i, state) in self.states.iter().enumerate() {
+            let angle = std::f64::consts::TAU * i as f64 / count;
+            let position = center + (Vector2D { x: 0.0, y: 200.0 }).rotate(-angle);
+            let mut state = state.borrow_mut();
+            (state.x, state.y) = position.into();
+        }
+
+        Machine {
+            states: self.states,
+            initial: self.initial,
+            properties: vec![],
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+// The reader for formats that are only imported, by file extension. Saving
+// never writes to these, so the diagram source isn't replaced by JSON.
+pub fn reader(path: &Path) -> Option<fn(&str) -> std::io::Result<Machine>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("mmd" | "md") => Some(mermaid),
+        Some("puml" | "plantuml" | "pu") => Some(plantuml),
+        _ => None,
+    }
+}
+
+fn invalid_data(line: usize, message: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        t!("import.line", line = line + 1, message = message),
+    )
+}
+
+// Splits `A --> B : label` style lines into source and target, ignoring the
+// label.
+fn parse_transition<'a>(line: &'a str, arrow: &str) -> Option<(&'a str, &'a str)> {
+    let (from, rest) = line.split_once(arrow)?;
+    let to = rest.split_once(':').map_or(rest, |(to, _)| to);
+
+    Some((from.trim(), to.trim()))
+}
+
+// Pseudo-states are declared with a stereotype, like `state Check <<choice>>`.
+// Other stereotypes, like forks, have no counterpart here.
+fn stereotype(declaration: &str) -> Option<Pseudo> {
+    if declaration.contains("<<choice>>") {
+        Some(Pseudo::Choice)
+    } else if declaration.contains("<<junction>>") {
+        Some(Pseudo::Junction)
+    } else {
+        None
+    }
+}
+
+// Parses a Mermaid `stateDiagram`/`stateDiagram-v2`. The diagram may be
+// embedded in Markdown, in which case only the first diagram is read.
+pub fn mermaid(text: &str) -> std::io::Result<Machine> {
+    let mut builder = Builder::new();
+    let mut lines = text.lines().enumerate();
+
+    lines
+        .by_ref()
+        .find(|(_, line)| line.trim_start().starts_with("stateDiagram"))
+        .ok_or_else(|| invalid_data(0, &t!("import.no_mermaid")))?;
+
+    let mut in_note = false;
+    for (number, line) in lines {
+        let line = line.trim();
+
+        if in_note {
+            in_note = line != "end note";
+            continue;
+        }
+
+        if line == "```" {
+            break;
+        }
+
+        if line.is_empty()
+            || line.starts_with("%%")
+            || line.starts_with("direction ")
+            || line.starts_with("classDef ")
+            || line.starts_with("class ")
+            || line == "}"
+            || line == "--"
+        {
+            continue;
+        }
+
+        if let Some(note) = line.strip_prefix("note ") {
+            in_note = !note.contains(':');
+            continue;
+        }
+
+        if let Some((from, to)) = parse_transition(line, "-->") {
+            match (from, to) {
+                ("[*]", "[*]") => {}
+                ("[*]", to) => builder.mark_initial(to),
+                (from, "[*]") => builder.mark_accepting(from),
+                (from, to) => builder.connect(from, to),
+            }
+        } else if let Some(state) = line.strip_prefix("state ") {
+            let state = state.trim_end_matches('{').trim();
+
+            if let Some((name, id)) = state.split_once(" as ") {
+                builder.rename(id.trim(), name.trim().trim_matches('"'));
+            } else {
+                let id = state.split_whitespace().next().unwrap_or(state);
+                builder.state(id).borrow_mut().pseudo = stereotype(state);
+            }
+        } else if let Some((id, name)) = line.split_once(':') {
+            builder.rename(id.trim(), name.trim());
+        } else if line.split_whitespace().count() == 1 {
+            builder.state(line);
+        } else {
+            return Err(invalid_data(number, &t!("import.unrecognized")));
+        }
+    }
+
+    Ok(builder.finish())
+}
+
+// PlantUML arrows may carry a direction or style between the dashes
+// (`-down->`, `-[#red]->`) and can point either way.
+fn parse_plantuml_arrow(line: &str) -> Option<(&str, &str)> {
+    let line = line.split_once(':').map_or(line, |(line, _)| line);
+
+    if let Some((left, right)) = line.split_once("->") {
+        let from = left.split_once('-').map_or(left, |(from, _)| from);
+        Some((from.trim(), right.trim()))
+    } else if let Some((left, right)) = line.split_once("<-") {
+        let from = right.rsplit_once('-').map_or(right, |(_, from)| from);
+        Some((from.trim(), left.trim()))
+    } else {
+        None
+    }
+}
+
+// Parses the state diagram between `@startuml` and `@enduml`.
+pub fn plantuml(text: &str) -> std::io::Result<Machine> {
+    let mut builder = Builder::new();
+    let mut lines = text.lines().enumerate();
+
+    lines
+        .by_ref()
+        .find(|(_, line)| line.trim_start().starts_with("@startuml"))
+        .ok_or_else(|| invalid_data(0, &t!("import.no_plantuml")))?;
+
+    let mut in_note = false;
+    for (number, line) in lines {
+        let line = line.trim();
+
+        if in_note {
+            in_note = line != "end note";
+            continue;
+        }
+
+        if line.starts_with("@enduml") {
+            break;
+        }
+
+        if line.is_empty()
+            || line.starts_with('\'')
+            || line.starts_with("skinparam ")
+            || line.starts_with("hide ")
+            || line.starts_with("title ")
+            || line == "}"
+            || line == "--"
+            || line == "||"
+        {
+            continue;
+        }
+
+        if let Some(note) = line.strip_prefix("note ") {
+            in_note = !note.contains(':');
+            continue;
+        }
+
+        if let Some(state) = line.strip_prefix("state ") {
+            let state = state.trim_end_matches('{').trim();
+            let (state, description) = match state.split_once(':') {
+                Some((state, description)) => (state.trim(), Some(description.trim())),
+                None => (state, None),
+            };
+
+            if let Some((name, id)) = state.split_once(" as ") {
+                builder.rename(id.trim(), name.trim().trim_matches('"'));
+            } else {
+                let id = state.split_whitespace().next().unwrap_or(state);
+                builder.state(id).borrow_mut().pseudo = stereotype(state);
+                if let Some(description) = description {
+                    builder.rename(id, description);
+                }
+            }
+        } else if let Some((from, to)) = parse_plantuml_arrow(line) {
+            match (from, to) {
+                ("[*]", "[*]") => {}
+                ("[*]", to) => builder.mark_initial(to),
+                (from, "[*]") => builder.mark_accepting(from),
+                (from, to) => builder.connect(from, to),
+            }
+        } else if let Some((id, name)) = line.split_once(':') {
+            builder.rename(id.trim(), name.trim());
+        } else {
+            return Err(invalid_data(number, &t!("import.unrecognized")));
+        }
+    }
+
+    Ok(builder.finish())
+}
+
+// Compiles a regex with `regex-automata` and loads the DFA it builds, for
+// inspecting what the engine makes of a pattern. States are numbered in the
+// order they are reached from the start, and transitions lose their bytes.
+pub fn regex(pattern: &str) -> std::io::Result<Machine> {
+    use regex_automata::dfa::{dense, Automaton, StartKind};
+    use regex_automata::util::start;
+    use regex_automata::{Anchored, MatchKind};
+
+    // Build errors keep the interesting part, like where the syntax error
+    // is, in their sources.
+    let invalid = |e: &dyn std::error::Error| {
+        let mut message = e.to_string();
+        let mut source = e.source();
+        while let Some(e) = source {
+            message += &format!(": {e}");
+            source = e.source();
+        }
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+    };
+
+    // Every match, not just the leftmost-first one, so the DFA keeps going
+    // after a match instead of stopping and shows the whole language.
+    let dfa = dense::Builder::new()
+        .configure(
+            dense::Config::new()
+                .start_kind(StartKind::Anchored)
+                .match_kind(MatchKind::All)
+                .minimize(true),
+        )
+        .build(pattern)
+        .map_err(|e| invalid(&e))?;
+    let start = dfa
+        .start_state(&start::Config::new().anchored(Anchored::Yes))
+        .map_err(|e| invalid(&e))?;
+
+    let mut builder = Builder::new();
+    let mut names = HashMap::new();
+    let mut queue = std::collections::VecDeque::from([start]);
+    names.insert(start, "q0".to_string());
+    builder.mark_initial("q0");
+
+    while let Some(id) = queue.pop_front() {
+        let name = names[&id].clone();
+        builder.state(&name);
+
+        // Matches are reported one byte late, so a state accepts when the
+        // end of the input leads to a match.
+        if dfa.is_match_state(dfa.next_eoi_state(id)) {
+            builder.mark_accepting(&name);
+        }
+
+        for unit in dfa.byte_classes().representatives(..) {
+            let Some(byte) = unit.as_u8() else {
+                continue;
+            };
+            let next = dfa.next_state(id, byte);
+            if dfa.is_dead_state(next) || dfa.is_quit_state(next) {
+                continue;
+            }
+
+            let count = names.len();
+            let next_name = names.entry(next).or_insert_with(|| {
+                queue.push_back(next);
+                format!("q{count}")
+            });
+            let next_name = next_name.clone();
+            builder.connect(&name, &next_name);
+        }
+    }
+
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The machine as sorted lines like `A -> B`, with `[*] -> A` for the
+    // initial state, `A -> [*]` for accepting ones and `A <<choice>>` for
+    // pseudo-states.
+    fn describe(machine: &Machine) -> Vec<String> {
+        let mut lines = vec![];
+        if let Some(initial) = machine.initial.upgrade() {
+            lines.push(format!("[*] -> {}", initial.borrow().name));
+        }
+        for state in &machine.states {
+            let state = state.borrow();
+            for next in state.next_states.iter().filter_map(Weak::upgrade) {
+                lines.push(format!("{} -> {}", state.name, next.borrow().name));
+            }
+            if state.accepting {
+                lines.push(format!("{} -> [*]", state.name));
+            }
+            match state.pseudo {
+                Some(Pseudo::Choice) => lines.push(format!("{} <<choice>>", state.name)),
+                Some(Pseudo::Junction) => lines.push(format!("{} <<junction>>", state.name)),
+                None => {}
+            }
+        }
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn reads_mermaid_from_markdown() {
+        let text = "# Door\n\n```mermaid\nstateDiagram-v2\n    direction LR\n    [*] --> Closed\n    state \"Wide open\" as Open\n    Closed --> Open : push\n    Open --> Closed\n    state Check <<choice>>\n    Closed --> Check\n    note right of Open\n        Mind the gap\n    end note\n    Open --> [*]\n```\n\nstateDiagram\n    Ignored --> Too\n";
+        let machine = mermaid(text).unwrap();
+
+        assert_eq!(
+            describe(&machine),
+            [
+                "Check <<choice>>",
+                "Closed -> Check",
+                "Closed -> Wide open",
+                "Wide open -> Closed",
+                "Wide open -> [*]",
+                "[*] -> Closed",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_mermaid() {
+        let error = mermaid("stateDiagram\n    A --> B\n    what is this\n")
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with("line 3:"));
+        assert!(mermaid("graph TD\n    A --> B\n").is_err());
+    }
+
+    #[test]
+    fn reads_plantuml() {
+        let text = "@startuml\n' a comment\nskinparam monochrome true\n[*] --> Idle\nIdle -down-> Busy : start\nIdle <- Busy\nstate Busy : Working\nstate Merge <<junction>>\nBusy -[#red]-> Merge\nMerge --> [*]\n@enduml\nIgnored --> Afterwards\n";
+        let machine = plantuml(text).unwrap();
+
+        assert_eq!(
+            describe(&machine),
+            [
+                "Idle -> Working",
+                "Merge -> [*]",
+                "Merge <<junction>>",
+                "Working -> Idle",
+                "Working -> Merge",
+                "[*] -> Idle",
+            ]
+        );
+        assert!(plantuml("@startuml\nA ~~ B\n@enduml\n").is_err());
+    }
+
+    #[test]
+    fn builds_the_dfa_of_a_regex() {
+        // The exact states depend on how the engine delays matches, but the
+        // start can't accept and the b* shows up as a loop that can.
+        let machine = regex("ab*").unwrap();
+        let lines = describe(&machine);
+        assert!(lines.contains(&"[*] -> q0".to_string()));
+        assert!(!lines.contains(&"q0 -> [*]".to_string()));
+        assert!(lines.iter().any(|line| {
+            let (from, to) = line.split_once(" -> ").unwrap();
+            from == to && lines.contains(&format!("{from} -> [*]"))
+        }));
+
+        let error = regex("a(b").err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn picks_readers_by_extension() {
+        assert!(reader(Path::new("door.mmd")).is_some());
+        assert!(reader(Path::new("README.md")).is_some());
+        assert!(reader(Path::new("door.puml")).is_some());
+        assert!(reader(Path::new("door.json")).is_none());
+        assert!(reader(Path::new("door")).is_none());
+    }
+}
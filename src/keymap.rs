@@ -0,0 +1,468 @@
+use std::fmt;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+// Everything that can be done from the canvas, independent of the key that
+// does it, so features can also be triggered by macros or scripts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Quit,
+    Save,
+    SaveAs,
+    Open,
+    NewState,
+    DeleteState,
+    MarkSource,
+    ToggleConnection,
+    ToggleAccepting,
+    MakeInitial,
+    CyclePseudoState,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    BendLeft,
+    BendRight,
+    BendUp,
+    BendDown,
+    Straighten,
+    SelectNext,
+    SelectPrevious,
+    Unselect,
+    ShortestPath,
+    ReplayPath,
+    Prune,
+    Layer,
+    Generate,
+    Undo,
+    Redo,
+    ToggleHistory,
+    PreviousHistoryEntry,
+    NextHistoryEntry,
+    GoToHistoryEntry,
+    ToggleDiagnostics,
+    PreviousIssue,
+    NextIssue,
+    ToggleProperties,
+    ToggleMetadata,
+    AddProperty,
+    RemoveProperty,
+    PreviousProperty,
+    NextProperty,
+    ChainPrompt,
+    CommandPrompt,
+    CycleMarker,
+    ToggleDepths,
+    ToggleClusters,
+    ToggleRulers,
+    ToggleHelp,
+    FilterPaths,
+    TogglePerf,
+    ToggleReadOnly,
+    ToggleListMode,
+    ToggleMonochrome,
+    ResetView,
+    Present,
+}
+
+impl Action {
+    // Actions that change the machine, and are refused in read-only mode.
+    pub fn edits(self) -> bool {
+        matches!(
+            self,
+            Self::NewState
+                | Self::DeleteState
+                | Self::ToggleConnection
+                | Self::ToggleAccepting
+                | Self::MakeInitial
+                | Self::CyclePseudoState
+                | Self::MoveLeft
+                | Self::MoveRight
+                | Self::MoveUp
+                | Self::MoveDown
+                | Self::BendLeft
+                | Self::BendRight
+                | Self::BendUp
+                | Self::BendDown
+                | Self::Straighten
+                | Self::Prune
+                | Self::Layer
+                | Self::Generate
+                | Self::Undo
+                | Self::Redo
+                | Self::GoToHistoryEntry
+                | Self::AddProperty
+                | Self::RemoveProperty
+                | Self::ChainPrompt
+        )
+    }
+}
+
+// Panels and modes some bindings only apply in. Those bindings take
+// precedence, so keys can mean something else while a panel is open.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum When {
+    #[default]
+    Always,
+    Diagnostics,
+    Properties,
+    History,
+    ListMode,
+}
+
+pub struct Binding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+    pub when: When,
+    pub action: Action,
+}
+
+// Names of the keys that aren't written as the character they type, as
+// profiles write them. Function keys are written `F1` to `F12`.
+const KEY_NAMES: [(KeyCode, &str); 16] = [
+    (KeyCode::Char(' '), "Space"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::BackTab, "BackTab"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Esc, "Esc"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+];
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match key_name(self.code, self.modifiers) {
+            Some(name) => write!(f, "{name}"),
+            // Not something a binding can be made for.
+            None => write!(f, "{:?}", self.code),
+        }
+    }
+}
+
+// The name `parse_key` reads back, like `Ctrl+s`, `Alt+Left` or `Enter`, if
+// the key has one.
+pub fn key_name(code: KeyCode, modifiers: KeyModifiers) -> Option<String> {
+    let key = match (KEY_NAMES.iter().find(|(c, _)| *c == code), code) {
+        (Some((_, name)), _) => name.to_string(),
+        (None, KeyCode::Char(ch)) => ch.to_string(),
+        (None, KeyCode::F(n)) => format!("F{n}"),
+        (None, _) => return None,
+    };
+
+    let mut name = String::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        name.push_str("Ctrl+");
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        name.push_str("Alt+");
+    }
+    name.push_str(&key);
+    Some(name)
+}
+
+// `text` without the modifier `prefix` it starts with, in any case.
+fn strip_modifier<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = text.get(prefix.len()..)?;
+    (text[..prefix.len()].eq_ignore_ascii_case(prefix) && !rest.is_empty()).then_some(rest)
+}
+
+// Keys named the way `key_name` names them, like `Ctrl+s`, `Alt+Left` or
+// `Enter`.
+pub fn parse_key(text: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = text;
+    loop {
+        if let Some(after) = strip_modifier(rest, "Ctrl+") {
+            modifiers |= KeyModifiers::CONTROL;
+            rest = after;
+        } else if let Some(after) = strip_modifier(rest, "Alt+") {
+            modifiers |= KeyModifiers::ALT;
+            rest = after;
+        } else {
+            break;
+        }
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(ch), None) => KeyCode::Char(ch),
+        // Names are matched regardless of case, so `enter` and `f1` work too.
+        _ => match rest.strip_prefix(['F', 'f']).and_then(|n| n.parse().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => {
+                KEY_NAMES
+                    .iter()
+                    .find(|(_, name)| name.eq_ignore_ascii_case(rest))?
+                    .0
+            }
+        },
+    };
+
+    Some((code, modifiers))
+}
+
+// A binding as written in profile files, e.g.
+// `{ "key": "Ctrl+s", "action": "Save" }` or
+// `{ "key": "[", "action": "PreviousIssue", "when": "Diagnostics" }`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub key: String,
+    pub action: Action,
+    #[serde(default)]
+    pub when: When,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        use KeyCode::{Char, Down, Enter, Esc, Left, Right, Tab, Up};
+
+        let always = [
+            (Char('q'), Quit),
+            (Char('S'), SaveAs),
+            (Char('o'), Open),
+            (Char('n'), NewState),
+            (Char('d'), DeleteState),
+            (Char('s'), MarkSource),
+            (Char('c'), ToggleConnection),
+            (Char('a'), ToggleAccepting),
+            (Char('i'), MakeInitial),
+            (Char('J'), CyclePseudoState),
+            (Left, MoveLeft),
+            (Right, MoveRight),
+            (Up, MoveUp),
+            (Down, MoveDown),
+            (Char('E'), Straighten),
+            (Tab, SelectNext),
+            (Esc, Unselect),
+            (Char('r'), ShortestPath),
+            (Char('w'), ReplayPath),
+            (Char('P'), Prune),
+            (Char('L'), Layer),
+            (Char('g'), Generate),
+            (Char('u'), Undo),
+            (Char('U'), Redo),
+            (Char('H'), ToggleHistory),
+            (Char('v'), ToggleDiagnostics),
+            (Char('A'), ToggleProperties),
+            (Char('D'), ToggleMetadata),
+            (Char('C'), ChainPrompt),
+            (Char(':'), CommandPrompt),
+            (Char('m'), CycleMarker),
+            (Char('b'), ToggleDepths),
+            (Char('K'), ToggleClusters),
+            (Char('G'), ToggleRulers),
+            (Char('?'), ToggleHelp),
+            (Char('f'), FilterPaths),
+            (Char('p'), TogglePerf),
+            (Char('R'), ToggleReadOnly),
+            (Char('T'), ToggleListMode),
+            (Char('M'), ToggleMonochrome),
+            (Char('0'), ResetView),
+            (Char('F'), Present),
+        ];
+        let with_alt = [
+            (Left, BendLeft),
+            (Right, BendRight),
+            (Up, BendUp),
+            (Down, BendDown),
+        ];
+        let in_panels = [
+            (When::Diagnostics, Char('['), PreviousIssue),
+            (When::Diagnostics, Char(']'), NextIssue),
+            (When::Properties, Char('+'), AddProperty),
+            (When::Properties, Char('-'), RemoveProperty),
+            (When::Properties, Char('<'), PreviousProperty),
+            (When::Properties, Char('>'), NextProperty),
+            (When::History, Char('{'), PreviousHistoryEntry),
+            (When::History, Char('}'), NextHistoryEntry),
+            (When::History, Enter, GoToHistoryEntry),
+            (When::ListMode, Up, SelectPrevious),
+            (When::ListMode, Down, SelectNext),
+        ];
+
+        let mut bindings = vec![Binding {
+            code: Char('s'),
+            modifiers: KeyModifiers::CONTROL,
+            when: When::Always,
+            action: Save,
+        }];
+        bindings.extend(always.into_iter().map(|(code, action)| Binding {
+            code,
+            modifiers: KeyModifiers::NONE,
+            when: When::Always,
+            action,
+        }));
+        bindings.extend(with_alt.into_iter().map(|(code, action)| Binding {
+            code,
+            modifiers: KeyModifiers::ALT,
+            when: When::Always,
+            action,
+        }));
+        bindings.extend(in_panels.into_iter().map(|(when, code, action)| Binding {
+            code,
+            modifiers: KeyModifiers::NONE,
+            when,
+            action,
+        }));
+
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn from_entries(entries: &[Entry]) -> Result<Self, String> {
+        let bindings = entries
+            .iter()
+            .map(|entry| {
+                let (code, modifiers) = parse_key(&entry.key)
+                    .ok_or_else(|| t!("keymap.unknown_key", key = format!("{:?}", entry.key)))?;
+                Ok(Binding {
+                    code,
+                    modifiers,
+                    when: entry.when,
+                    action: entry.action,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { bindings })
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.bindings
+            .iter()
+            .map(|b| Entry {
+                key: b.to_string(),
+                action: b.action,
+                when: b.when,
+            })
+            .collect()
+    }
+
+    // `active` tells whether the panel or mode of a binding is open.
+    pub fn action(&self, key: KeyEvent, active: impl Fn(When) -> bool) -> Option<Action> {
+        // Shift is part of the character already, `S` arrives as shift+S.
+        let modifiers = key.modifiers.difference(KeyModifiers::SHIFT);
+        let matching = |b: &&Binding| b.code == key.code && b.modifiers == modifiers;
+
+        self.bindings
+            .iter()
+            .filter(|b| b.when != When::Always && active(b.when))
+            .find(matching)
+            .or_else(|| {
+                self.bindings
+                    .iter()
+                    .filter(|b| b.when == When::Always)
+                    .find(matching)
+            })
+            .map(|b| b.action)
+    }
+
+    // Keys bound to more than one action in the same panel or mode, where
+    // only the first would ever run.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = vec![];
+
+        for (i, a) in self.bindings.iter().enumerate() {
+            for b in &self.bindings[i + 1..] {
+                if a.code == b.code
+                    && a.modifiers == b.modifiers
+                    && a.when == b.when
+                    && a.action != b.action
+                {
+                    conflicts.push(t!(
+                        "keymap.conflict",
+                        key = a,
+                        first = format!("{:?}", a.action),
+                        second = format!("{:?}", b.action)
+                    ));
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            parse_key("s"),
+            Some((KeyCode::Char('s'), KeyModifiers::NONE))
+        );
+        assert_eq!(
+            parse_key("Ctrl+Alt+Left"),
+            Some((KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::ALT))
+        );
+        assert_eq!(
+            parse_key("Ctrl++"),
+            Some((KeyCode::Char('+'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(parse_key("F5"), Some((KeyCode::F(5), KeyModifiers::NONE)));
+        assert_eq!(parse_key("F13"), None);
+        assert_eq!(
+            parse_key("enter"),
+            Some((KeyCode::Enter, KeyModifiers::NONE))
+        );
+        assert_eq!(
+            parse_key("Alt+f1"),
+            Some((KeyCode::F(1), KeyModifiers::ALT))
+        );
+        assert_eq!(
+            parse_key("ctrl+ALT+s"),
+            Some((
+                KeyCode::Char('s'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            ))
+        );
+        assert_eq!(
+            parse_key("Space"),
+            Some((KeyCode::Char(' '), KeyModifiers::NONE))
+        );
+        assert_eq!(parse_key("Ctrl+"), None);
+        assert_eq!(parse_key("Spacebar"), None);
+    }
+
+    #[test]
+    fn displays_keys_the_way_they_parse() {
+        let codes = KEY_NAMES.iter().map(|(code, _)| *code).chain([
+            KeyCode::Char('x'),
+            KeyCode::F(1),
+            KeyCode::F(12),
+        ]);
+        for code in codes {
+            for modifiers in [
+                KeyModifiers::NONE,
+                KeyModifiers::CONTROL | KeyModifiers::ALT,
+            ] {
+                let binding = Binding {
+                    code,
+                    modifiers,
+                    when: When::Always,
+                    action: Action::Quit,
+                };
+                assert_eq!(parse_key(&binding.to_string()), Some((code, modifiers)));
+            }
+        }
+    }
+}
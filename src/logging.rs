@@ -0,0 +1,18 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Sends `tracing` events to a file given with `--log`, since anything printed
+// would end up under the full screen interface. Without it events go nowhere.
+pub fn init(path: impl AsRef<Path>) -> std::io::Result<()> {
+    let file = File::create(path)?;
+
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_target(false)
+        .init();
+
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), "started");
+    Ok(())
+}
@@ -0,0 +1,760 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::{Rc, Weak};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::properties::Property;
+use crate::vector2d::Vector2D;
+use crate::{FSMState, Pseudo};
+
+pub struct Machine {
+    pub states: Vec<Rc<RefCell<FSMState>>>,
+    pub initial: Weak<RefCell<FSMState>>,
+    pub properties: Vec<Property>,
+    pub metadata: Metadata,
+}
+
+// About the document as a whole rather than any state. Empty fields are
+// unset.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Metadata {
+    pub title: String,
+    pub author: String,
+    pub description: String,
+}
+
+// The first bytes of a gzip stream, which tell compressed files apart.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Bump this whenever the layout below changes, and add a step to
+// `MIGRATIONS` that upgrades files written by the previous version.
+const FORMAT_VERSION: u64 = 6;
+
+// `MIGRATIONS[n]` upgrades a version `n` file to version `n + 1`.
+const MIGRATIONS: [fn(&mut Value); FORMAT_VERSION as usize] = [
+    migrate_v0, migrate_v1, migrate_v2, migrate_v3, migrate_v4, migrate_v5,
+];
+
+// On-disk representation of a machine. Transitions refer to their target by
+// index into `states`, since the in-memory graph uses weak pointers.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct MachineFile {
+    version: u64,
+    states: Vec<StateEntry>,
+    initial: Option<usize>,
+    // Stored as the text they were declared with.
+    properties: Vec<String>,
+    metadata: Metadata,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct StateEntry {
+    name: String,
+    x: f64,
+    y: f64,
+    accepting: bool,
+    pseudo: Option<Pseudo>,
+    next_states: Vec<usize>,
+    bends: Vec<Bend>,
+    notes: Vec<Note>,
+}
+
+// Free text on the transition to state `to`.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Note {
+    to: usize,
+    text: String,
+}
+
+// The control point of a curved transition, as an offset from the middle of
+// the straight line to state `to`.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Bend {
+    to: usize,
+    x: f64,
+    y: f64,
+}
+
+fn encode(machine: &Machine) -> MachineFile {
+    let states = &machine.states;
+    let indices: HashMap<*const RefCell<FSMState>, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (Rc::as_ptr(s), i))
+        .collect();
+
+    MachineFile {
+        version: FORMAT_VERSION,
+        states: states
+            .iter()
+            .map(|s| {
+                let s = s.borrow();
+                StateEntry {
+                    name: s.name.clone(),
+                    x: s.x,
+                    y: s.y,
+                    accepting: s.accepting,
+                    pseudo: s.pseudo,
+                    next_states: s
+                        .next_states
+                        .iter()
+                        .filter_map(|n| indices.get(&n.as_ptr()).copied())
+                        .collect(),
+                    bends: s
+                        .next_states
+                        .iter()
+                        .filter_map(|n| {
+                            let bend = s.bend(n.as_ptr())?;
+                            Some(Bend {
+                                to: *indices.get(&n.as_ptr())?,
+                                x: bend.x,
+                                y: bend.y,
+                            })
+                        })
+                        .collect(),
+                    notes: s
+                        .next_states
+                        .iter()
+                        .filter_map(|n| {
+                            Some(Note {
+                                to: *indices.get(&n.as_ptr())?,
+                                text: s.note(n.as_ptr())?.to_string(),
+                            })
+                        })
+                        .collect(),
+                }
+            })
+            .collect(),
+        initial: indices.get(&machine.initial.as_ptr()).copied(),
+        properties: machine.properties.iter().map(|p| p.to_string()).collect(),
+        metadata: machine.metadata.clone(),
+    }
+}
+
+pub fn save(path: impl AsRef<Path>, machine: &Machine) -> std::io::Result<()> {
+    write(path, &encode(machine))
+}
+
+fn write(path: impl AsRef<Path>, file: &MachineFile) -> std::io::Result<()> {
+    let compress = path.as_ref().extension().is_some_and(|e| e == "gz");
+    let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    if compress {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        serde_json::to_writer(&mut encoder, file)?;
+        encoder.finish()?.flush()
+    } else {
+        let mut writer = writer;
+        serde_json::to_writer(&mut writer, file)?;
+        writer.flush()
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> std::io::Result<Machine> {
+    read(std::io::BufReader::new(std::fs::File::open(path)?))
+}
+
+// Encodes a machine the way `save` writes it to an uncompressed file.
+pub fn to_json(machine: &Machine) -> std::io::Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&encode(machine))?)
+}
+
+pub fn read(mut reader: impl BufRead) -> std::io::Result<Machine> {
+    // Compressed files are recognized by their magic bytes rather than the
+    // extension, so renamed files still load.
+    let file = if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        migrate(serde_json::from_reader(GzDecoder::new(reader))?)?
+    } else {
+        migrate(serde_json::from_reader(reader)?)?
+    };
+
+    decode(&file)
+}
+
+fn decode(file: &MachineFile) -> std::io::Result<Machine> {
+    let states: Vec<_> = file
+        .states
+        .iter()
+        .map(|s| {
+            let mut state = FSMState::new(s.name.clone(), s.x, s.y);
+            state.accepting = s.accepting;
+            state.pseudo = s.pseudo;
+            Rc::new(RefCell::new(state))
+        })
+        .collect();
+
+    for (entry, state) in file.states.iter().zip(&states) {
+        for &next in &entry.next_states {
+            let Some(next_state) = states.get(next) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    t!(
+                        "file.missing_state",
+                        name = format!("{:?}", entry.name),
+                        index = next
+                    ),
+                ));
+            };
+
+            state
+                .borrow_mut()
+                .next_states
+                .push(Rc::downgrade(next_state));
+        }
+
+        for bend in &entry.bends {
+            let Some(next_state) = states.get(bend.to) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    t!(
+                        "file.missing_bend",
+                        name = format!("{:?}", entry.name),
+                        index = bend.to
+                    ),
+                ));
+            };
+
+            let offset = Vector2D {
+                x: bend.x,
+                y: bend.y,
+            };
+            state.borrow_mut().set_bend(next_state, Some(offset));
+        }
+
+        for note in &entry.notes {
+            let Some(next_state) = states.get(note.to) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    t!(
+                        "file.missing_note",
+                        name = format!("{:?}", entry.name),
+                        index = note.to
+                    ),
+                ));
+            };
+
+            state
+                .borrow_mut()
+                .set_note(next_state, Some(note.text.clone()));
+        }
+    }
+
+    let initial = match file.initial {
+        Some(initial) => Rc::downgrade(states.get(initial).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                t!("file.missing_initial", index = initial),
+            )
+        })?),
+        None => Weak::new(),
+    };
+
+    let properties = file
+        .properties
+        .iter()
+        .map(|p| {
+            Property::parse(p).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    t!("file.bad_property", property = format!("{p:?}")),
+                )
+            })
+        })
+        .collect::<std::io::Result<_>>()?;
+
+    Ok(Machine {
+        states,
+        initial,
+        properties,
+        metadata: file.metadata.clone(),
+    })
+}
+
+// A copy of a machine in its saved form, kept in memory for undo.
+#[derive(Clone, PartialEq)]
+pub struct Snapshot(MachineFile);
+
+// The names of the states `state` has transitions to.
+fn targets<'a>(state: &StateEntry, names: &[&'a str]) -> Vec<&'a str> {
+    state.next_states.iter().map(|&n| names[n]).collect()
+}
+
+impl Snapshot {
+    pub fn take(machine: &Machine) -> Self {
+        Self(encode(machine))
+    }
+
+    // Unlike the machine itself, a snapshot can be saved from any thread.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        write(path, &self.0)
+    }
+
+    pub fn restore(&self) -> Machine {
+        decode(&self.0).expect("snapshots are taken from consistent machines")
+    }
+
+    // What changed from `self` to `newer`, matching states by name. A state
+    // that disappears where another one appears counts as renamed.
+    pub fn changes(&self, newer: &Snapshot) -> Vec<String> {
+        let (old, new) = (&self.0, &newer.0);
+        let (old_states, new_states): (HashMap<&str, &StateEntry>, HashMap<&str, &StateEntry>) = (
+            old.states.iter().map(|s| (s.name.as_str(), s)).collect(),
+            new.states.iter().map(|s| (s.name.as_str(), s)).collect(),
+        );
+        let mut changes = vec![];
+        let mut renames = HashMap::new();
+
+        let mut removed: Vec<&StateEntry> = old
+            .states
+            .iter()
+            .filter(|s| !new_states.contains_key(s.name.as_str()))
+            .collect();
+        for state in new
+            .states
+            .iter()
+            .filter(|s| !old_states.contains_key(s.name.as_str()))
+        {
+            match removed
+                .iter()
+                .position(|r| r.x == state.x && r.y == state.y)
+            {
+                Some(i) => {
+                    let renamed = removed.remove(i);
+                    changes.push(t!("change.renamed", from = renamed.name, to = state.name));
+                    renames.insert(renamed.name.clone(), state.name.clone());
+                }
+                None => changes.push(t!("change.added", name = state.name)),
+            }
+        }
+        changes.extend(removed.iter().map(|s| t!("change.deleted", name = s.name)));
+
+        for state in &new.states {
+            let Some(before) = old_states.get(state.name.as_str()) else {
+                continue;
+            };
+            if (before.x, before.y) != (state.x, state.y) {
+                changes.push(t!("change.moved", name = state.name));
+            }
+            if before.accepting != state.accepting {
+                changes.push(match state.accepting {
+                    true => t!("change.accepting", name = state.name),
+                    false => t!("change.not_accepting", name = state.name),
+                });
+            }
+            if before.pseudo != state.pseudo {
+                changes.push(match state.pseudo {
+                    Some(Pseudo::Choice) => t!("change.choice", name = state.name),
+                    Some(Pseudo::Junction) => t!("change.junction", name = state.name),
+                    None => t!("change.regular", name = state.name),
+                });
+            }
+        }
+
+        // Names in the older machine, as they are called after renames.
+        let old_names: Vec<&str> = old
+            .states
+            .iter()
+            .map(|s| renames.get(&s.name).unwrap_or(&s.name).as_str())
+            .collect();
+        let new_names: Vec<&str> = new.states.iter().map(|s| s.name.as_str()).collect();
+
+        // Transitions are compared state by state, and most states keep
+        // theirs as they were, so big machines don't need the whole graph
+        // hashed on every edit.
+        let old_index: HashMap<&str, usize> = old_names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (name, i))
+            .collect();
+        let mut matched = vec![false; old.states.len()];
+        let (mut reshaped, mut noted, mut connected, mut disconnected) =
+            (vec![], vec![], vec![], vec![]);
+        for (j, state) in new.states.iter().enumerate() {
+            let from = new_names[j];
+            let new_targets = targets(state, &new_names);
+            let Some(&i) = old_index.get(from) else {
+                connected.extend(new_targets.iter().map(|&to| (from, to)));
+                continue;
+            };
+            matched[i] = true;
+            let before = &old.states[i];
+            let old_targets = targets(before, &old_names);
+
+            let kept = match old_targets == new_targets {
+                true => new_targets,
+                false => {
+                    let (old_set, new_set): (HashSet<&str>, HashSet<&str>) = (
+                        old_targets.iter().copied().collect(),
+                        new_targets.iter().copied().collect(),
+                    );
+                    connected.extend(
+                        new_targets
+                            .iter()
+                            .filter(|to| !old_set.contains(*to))
+                            .map(|&to| (from, to)),
+                    );
+                    disconnected.extend(
+                        old_targets
+                            .iter()
+                            .filter(|to| !new_set.contains(*to))
+                            .map(|&to| (from, to)),
+                    );
+                    new_targets
+                        .into_iter()
+                        .filter(|to| old_set.contains(to))
+                        .collect()
+                }
+            };
+
+            if !before.bends.is_empty() || !state.bends.is_empty() {
+                let bend = |s: &StateEntry, names: &[&str], to: &str| {
+                    s.bends
+                        .iter()
+                        .find(|b| names[b.to] == to)
+                        .map(|b| (b.x, b.y))
+                };
+                for &to in &kept {
+                    match (bend(before, &old_names, to), bend(state, &new_names, to)) {
+                        (Some(_), None) => {
+                            reshaped.push(t!("change.straightened", from = from, to = to))
+                        }
+                        (before, Some(after)) if before != Some(after) => {
+                            reshaped.push(t!("change.bent", from = from, to = to))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            if !before.notes.is_empty() || !state.notes.is_empty() {
+                let note = |s: &StateEntry, names: &[&str], to: &str| {
+                    s.notes
+                        .iter()
+                        .find(|n| names[n.to] == to)
+                        .map(|n| n.text.clone())
+                };
+                for &to in &kept {
+                    match (note(before, &old_names, to), note(state, &new_names, to)) {
+                        (Some(_), None) => {
+                            noted.push(t!("change.note_removed", from = from, to = to))
+                        }
+                        (before, Some(after)) if before.as_ref() != Some(&after) => {
+                            noted.push(t!("change.noted", from = from, to = to))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        for (i, state) in old.states.iter().enumerate().filter(|(i, _)| !matched[*i]) {
+            let from = old_names[i];
+            disconnected.extend(targets(state, &old_names).into_iter().map(|to| (from, to)));
+        }
+
+        changes.extend(reshaped);
+        changes.extend(noted);
+        changes.extend(
+            connected
+                .iter()
+                .map(|(from, to)| t!("change.connected", from = from, to = to)),
+        );
+        changes.extend(
+            disconnected
+                .iter()
+                .map(|(from, to)| t!("change.disconnected", from = from, to = to)),
+        );
+
+        if old.initial.map(|i| old_names[i]) != new.initial.map(|i| new_names[i]) {
+            changes.push(match new.initial {
+                Some(i) => t!("change.initial", name = new_names[i]),
+                None => t!("change.no_initial"),
+            });
+        }
+
+        for property in new
+            .properties
+            .iter()
+            .filter(|p| !old.properties.contains(p))
+        {
+            changes.push(t!("change.property_added", property = property));
+        }
+        for property in old
+            .properties
+            .iter()
+            .filter(|p| !new.properties.contains(p))
+        {
+            changes.push(t!("change.property_removed", property = property));
+        }
+
+        let fields = [
+            (t!("change.title"), &old.metadata.title, &new.metadata.title),
+            (
+                t!("change.author"),
+                &old.metadata.author,
+                &new.metadata.author,
+            ),
+            (
+                t!("change.description"),
+                &old.metadata.description,
+                &new.metadata.description,
+            ),
+        ];
+        for (change, before, after) in fields {
+            if before != after {
+                changes.push(change);
+            }
+        }
+
+        changes
+    }
+}
+
+// Files written before versioning was introduced have no version field, and
+// may lack accepting flags.
+fn migrate_v0(file: &mut Value) {
+    if let Some(states) = file.get_mut("states").and_then(Value::as_array_mut) {
+        for state in states.iter_mut().filter_map(Value::as_object_mut) {
+            state.entry("accepting").or_insert(Value::Bool(false));
+        }
+    }
+}
+
+// Version 1 files predate properties.
+fn migrate_v1(file: &mut Value) {
+    if let Some(file) = file.as_object_mut() {
+        file.insert("properties".to_string(), Value::Array(vec![]));
+    }
+}
+
+// Version 2 files predate curved transitions.
+fn migrate_v2(file: &mut Value) {
+    if let Some(states) = file.get_mut("states").and_then(Value::as_array_mut) {
+        for state in states.iter_mut().filter_map(Value::as_object_mut) {
+            state.insert("bends".to_string(), Value::Array(vec![]));
+        }
+    }
+}
+
+// Version 3 files predate transition notes.
+fn migrate_v3(file: &mut Value) {
+    if let Some(states) = file.get_mut("states").and_then(Value::as_array_mut) {
+        for state in states.iter_mut().filter_map(Value::as_object_mut) {
+            state.insert("notes".to_string(), Value::Array(vec![]));
+        }
+    }
+}
+
+// Version 4 files predate metadata.
+fn migrate_v4(file: &mut Value) {
+    if let Some(file) = file.as_object_mut() {
+        file.insert(
+            "metadata".to_string(),
+            serde_json::to_value(Metadata::default()).unwrap_or_default(),
+        );
+    }
+}
+
+// Version 5 files predate pseudo-states.
+fn migrate_v5(file: &mut Value) {
+    if let Some(states) = file.get_mut("states").and_then(Value::as_array_mut) {
+        for state in states.iter_mut().filter_map(Value::as_object_mut) {
+            state.insert("pseudo".to_string(), Value::Null);
+        }
+    }
+}
+
+fn migrate(mut file: Value) -> std::io::Result<MachineFile> {
+    let version = file.get("version").map_or(Some(0), Value::as_u64);
+
+    let Some(version) = version.filter(|v| *v <= FORMAT_VERSION) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            t!(
+                "file.version",
+                version = file["version"],
+                supported = FORMAT_VERSION
+            ),
+        ));
+    };
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut file);
+    }
+
+    if let Some(file) = file.as_object_mut() {
+        file.insert("version".to_string(), FORMAT_VERSION.into());
+    }
+
+    Ok(serde_json::from_value(file)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // States named by letter in a row, connected as listed by index.
+    fn machine(names: &str, transitions: &[(usize, usize)]) -> Machine {
+        let states: Vec<_> = names
+            .chars()
+            .enumerate()
+            .map(|(i, name)| {
+                let state = FSMState::new(name.to_string(), i as f64 * 100.0, 0.0);
+                Rc::new(RefCell::new(state))
+            })
+            .collect();
+        for &(from, to) in transitions {
+            states[from]
+                .borrow_mut()
+                .next_states
+                .push(Rc::downgrade(&states[to]));
+        }
+
+        Machine {
+            initial: Rc::downgrade(&states[0]),
+            states,
+            properties: vec![],
+            metadata: Metadata::default(),
+        }
+    }
+
+    fn changes(before: &Machine, after: &Machine) -> Vec<String> {
+        Snapshot::take(before).changes(&Snapshot::take(after))
+    }
+
+    #[test]
+    fn connections() {
+        let before = machine("ABC", &[(0, 1), (1, 2)]);
+        let after = machine("ABC", &[(0, 1), (0, 2)]);
+
+        assert_eq!(
+            changes(&before, &after),
+            ["Connected A -> C", "Disconnected B -> C"]
+        );
+    }
+
+    #[test]
+    fn renaming_keeps_transitions() {
+        let before = machine("AB", &[(0, 1), (1, 0)]);
+        let after = machine("AB", &[(0, 1), (1, 0)]);
+        after.states[1].borrow_mut().name = "X".to_string();
+
+        assert_eq!(changes(&before, &after), ["Renamed B to X"]);
+    }
+
+    #[test]
+    fn deleting_disconnects() {
+        let before = machine("ABC", &[(0, 1), (1, 2)]);
+        let after = machine("AB", &[(0, 1)]);
+        after.states[1].borrow_mut().x = 500.0;
+
+        assert_eq!(
+            changes(&before, &after),
+            ["Deleted C", "Moved B", "Disconnected B -> C"]
+        );
+    }
+
+    #[test]
+    fn bends_and_notes() {
+        let before = machine("AB", &[(0, 1)]);
+        let after = machine("AB", &[(0, 1)]);
+        let mut a = after.states[0].borrow_mut();
+        a.set_bend(&after.states[1], Some(Vector2D { x: 0.0, y: 10.0 }));
+        a.set_note(&after.states[1], Some("go".to_string()));
+        drop(a);
+
+        assert_eq!(changes(&before, &after), ["Bent A -> B", "Noted A -> B"]);
+        assert_eq!(
+            changes(&after, &before),
+            ["Straightened A -> B", "Removed the note on A -> B"]
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let before = machine("AB", &[(0, 1)]);
+        assert!(changes(&before, &before).is_empty());
+    }
+
+    fn temporary(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("fsmtui-{}-{name}", std::process::id()))
+    }
+
+    fn round_trip(name: &str) {
+        let mut original = machine("ABC", &[(0, 1), (1, 2), (2, 2)]);
+        {
+            let mut a = original.states[0].borrow_mut();
+            a.accepting = true;
+            a.set_bend(&original.states[1], Some(Vector2D { x: 3.0, y: -4.0 }));
+            a.set_note(&original.states[1], Some("push".to_string()));
+        }
+        original.states[2].borrow_mut().pseudo = Some(Pseudo::Choice);
+        original.properties = vec![Property::parse("C is reachable from A").unwrap()];
+        original.metadata.title = "Door".to_string();
+
+        let path = temporary(name);
+        save(&path, &original).unwrap();
+        let loaded = load(&path);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(Snapshot::take(&loaded.unwrap()) == Snapshot::take(&original));
+        assert_eq!(bytes.starts_with(&GZIP_MAGIC), name.ends_with(".gz"));
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        round_trip("machine.json");
+    }
+
+    #[test]
+    fn saves_and_loads_compressed() {
+        round_trip("machine.json.gz");
+    }
+
+    #[test]
+    fn migrates_unversioned_files() {
+        let text = r#"{
+            "states": [
+                { "name": "A", "x": 0, "y": 0, "next_states": [1] },
+                { "name": "B", "x": 100, "y": 0, "next_states": [] }
+            ],
+            "initial": 0
+        }"#;
+        let machine = read(text.as_bytes()).unwrap();
+
+        assert_eq!(machine.states.len(), 2);
+        let a = machine.states[0].borrow();
+        assert!(!a.accepting && a.pseudo.is_none());
+        assert!(a.bends.is_empty() && a.notes.is_empty());
+        assert!(Rc::ptr_eq(
+            &machine.initial.upgrade().unwrap(),
+            &machine.states[0]
+        ));
+        assert!(machine.properties.is_empty());
+        assert!(machine.metadata == Metadata::default());
+    }
+
+    #[test]
+    fn migrates_every_version() {
+        for version in 0..FORMAT_VERSION {
+            let mut file = serde_json::to_value(encode(&machine("AB", &[(0, 1)]))).unwrap();
+            file["version"] = version.into();
+            let migrated = migrate(file).unwrap();
+            assert_eq!(migrated.version, FORMAT_VERSION);
+        }
+    }
+
+    #[test]
+    fn rejects_newer_files() {
+        let text = r#"{ "version": 999, "states": [], "initial": null }"#;
+        let error = read(text.as_bytes()).err().unwrap();
+        assert!(error.to_string().contains("version 999"));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+// Keeps a mistyped count from queueing millions of keys.
+const MAX_REPEAT: usize = 1000;
+
+// Keyboard macros kept in registers named by a letter. `Q` followed by a
+// letter starts recording into that register and a second `Q` stops. `@`
+// followed by an optional count and a letter plays it back, and `@@` repeats
+// the last register played.
+#[derive(Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    recording: Option<(char, Vec<KeyEvent>)>,
+    // Set after `Q` or `@` while the rest of the command is typed.
+    pending: Option<Pending>,
+    last_played: Option<char>,
+}
+
+enum Pending {
+    Record,
+    Play { count: String },
+}
+
+pub enum Outcome {
+    // The key was part of a macro command and is done with.
+    Handled,
+    // The key should be handled as usual.
+    Pass,
+    Recording(char),
+    Recorded(char, usize),
+    Play(Vec<KeyEvent>),
+    Error(String),
+}
+
+impl Macros {
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    // `can_start` is false while text is being typed in, so `Q` and `@` can
+    // still be typed into names.
+    pub fn handle(&mut self, key: KeyEvent, can_start: bool) -> Outcome {
+        if let Some(pending) = self.pending.take() {
+            return self.finish(pending, key);
+        }
+
+        if can_start {
+            match key.code {
+                KeyCode::Char('Q') => {
+                    if let Some((register, keys)) = self.recording.take() {
+                        let count = keys.len();
+                        self.registers.insert(register, keys);
+                        return Outcome::Recorded(register, count);
+                    }
+                    self.pending = Some(Pending::Record);
+                    return Outcome::Handled;
+                }
+                KeyCode::Char('@') => {
+                    self.pending = Some(Pending::Play {
+                        count: String::new(),
+                    });
+                    return Outcome::Handled;
+                }
+                _ => (),
+            }
+        }
+
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+        Outcome::Pass
+    }
+
+    fn finish(&mut self, pending: Pending, key: KeyEvent) -> Outcome {
+        let KeyCode::Char(ch) = key.code else {
+            return Outcome::Handled;
+        };
+
+        match pending {
+            Pending::Record if ch.is_ascii_lowercase() => {
+                self.recording = Some((ch, vec![]));
+                Outcome::Recording(ch)
+            }
+            Pending::Record => Outcome::Error(t!("status.macro_register")),
+            Pending::Play { mut count } if ch.is_ascii_digit() => {
+                count.push(ch);
+                self.pending = Some(Pending::Play { count });
+                Outcome::Handled
+            }
+            Pending::Play { count } => {
+                let register = match ch {
+                    '@' => match self.last_played {
+                        Some(register) => register,
+                        None => return Outcome::Error(t!("status.macro_none")),
+                    },
+                    ch if ch.is_ascii_lowercase() => ch,
+                    _ => return Outcome::Error(t!("status.macro_register")),
+                };
+
+                if self.recording() == Some(register) {
+                    return Outcome::Error(t!("status.macro_busy", register = register));
+                }
+
+                let Some(keys) = self.registers.get(&register) else {
+                    return Outcome::Error(t!("status.macro_empty", register = register));
+                };
+
+                let times = count.parse::<usize>().unwrap_or(1).clamp(1, MAX_REPEAT);
+                self.last_played = Some(register);
+                Outcome::Play(keys.repeat(times))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(ch: char) -> KeyEvent {
+        KeyEvent::from(KeyCode::Char(ch))
+    }
+
+    fn typed(macros: &mut Macros, text: &str) -> Vec<Outcome> {
+        text.chars()
+            .map(|ch| macros.handle(key(ch), true))
+            .collect()
+    }
+
+    fn record(macros: &mut Macros, register: char, text: &str) {
+        typed(macros, &format!("Q{register}{text}Q"));
+    }
+
+    #[test]
+    fn records_into_a_register() {
+        let mut macros = Macros::default();
+        assert!(matches!(macros.handle(key('Q'), true), Outcome::Handled));
+        assert!(matches!(
+            macros.handle(key('a'), true),
+            Outcome::Recording('a')
+        ));
+        assert_eq!(macros.recording(), Some('a'));
+        assert!(matches!(macros.handle(key('n'), true), Outcome::Pass));
+        assert!(matches!(macros.handle(key('x'), true), Outcome::Pass));
+        assert!(matches!(
+            macros.handle(key('Q'), true),
+            Outcome::Recorded('a', 2)
+        ));
+        assert_eq!(macros.recording(), None);
+    }
+
+    #[test]
+    fn replays_registers() {
+        let mut macros = Macros::default();
+        record(&mut macros, 'a', "nx");
+
+        let outcomes = typed(&mut macros, "@3a");
+        let Some(Outcome::Play(keys)) = outcomes.last() else {
+            panic!("the macro was not played");
+        };
+        assert_eq!(keys, &"nxnxnx".chars().map(key).collect::<Vec<_>>());
+
+        let outcomes = typed(&mut macros, "@@");
+        assert!(matches!(outcomes.last(), Some(Outcome::Play(keys)) if keys.len() == 2));
+
+        let outcomes = typed(&mut macros, "@99999a");
+        assert!(
+            matches!(outcomes.last(), Some(Outcome::Play(keys)) if keys.len() == 2 * MAX_REPEAT)
+        );
+    }
+
+    #[test]
+    fn refuses_to_replay_the_register_being_recorded() {
+        let mut macros = Macros::default();
+        record(&mut macros, 'a', "n");
+        typed(&mut macros, "Qa");
+
+        let outcomes = typed(&mut macros, "@a");
+        assert!(
+            matches!(outcomes.last(), Some(Outcome::Error(message)) if message == "Register a is being recorded.")
+        );
+    }
+
+    #[test]
+    fn reports_bad_registers() {
+        let mut macros = Macros::default();
+        let error = |outcomes: Vec<Outcome>| match outcomes.last() {
+            Some(Outcome::Error(message)) => message.clone(),
+            _ => panic!("expected an error"),
+        };
+
+        assert_eq!(error(typed(&mut macros, "@b")), "Register b is empty.");
+        assert_eq!(error(typed(&mut macros, "@@")), t!("status.macro_none"));
+        assert_eq!(
+            error(typed(&mut macros, "@1A")),
+            t!("status.macro_register")
+        );
+    }
+
+    #[test]
+    fn leaves_typed_text_alone() {
+        let mut macros = Macros::default();
+        assert!(matches!(macros.handle(key('Q'), false), Outcome::Pass));
+        assert!(matches!(macros.handle(key('@'), false), Outcome::Pass));
+        assert_eq!(macros.recording(), None);
+    }
+}
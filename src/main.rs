@@ -1,56 +1,506 @@
-#![feature(let_chains)]
-
 use core::f64;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::{Rc, Weak};
+use std::sync::Arc;
 
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode},
-    layout::{Constraint, Layout},
+    crossterm::{
+        event::{
+            self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste,
+            EnableMouseCapture, Event, KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind,
+        },
+        execute,
+    },
+    layout::Rect,
     style::Color,
     symbols::Marker,
-    widgets::{
-        canvas::{Canvas, Circle, Context, Line},
-        Block, Paragraph, Widget,
-    },
-    DefaultTerminal, Frame,
+    DefaultTerminal,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+// Declared first so every module can use `t!`.
+#[macro_use]
+mod i18n;
+mod analysis;
+mod commands;
+mod config;
+mod diagnostics;
+mod emergency;
+mod events;
+mod export;
+mod file_browser;
+mod generator;
+mod graphics;
+mod history;
+mod import;
+mod keymap;
+mod logging;
+mod machine_file;
+mod macros;
+mod profile;
+mod project;
+mod properties;
+mod recording;
+mod spatial_index;
+mod storage;
+mod surface;
+mod tutorial;
 mod vector2d;
+mod view;
+mod web_view;
+mod writer;
+use config::Config;
+use events::{AppEvent, Events};
+use file_browser::FileBrowser;
+use history::History;
+use keymap::{Action, Keymap, When};
+use machine_file::{Machine, Metadata, Snapshot};
+use macros::Macros;
+use properties::Property;
+use recording::{Recorder, Replay};
+use spatial_index::SpatialIndex;
+use surface::Surface;
 use vector2d::Vector2D;
+use web_view::WebView;
+use writer::{Job, Writer, Written};
+
+// Ticks drive everything that moves on its own. Replays and path walks step
+// once every `TICKS_PER_STEP` ticks, while animations can use every tick.
+const TICK_RATE: std::time::Duration = std::time::Duration::from_millis(50);
+const TICKS_PER_STEP: u64 = 5;
+// Selected states gain and lose a ring every this many ticks.
+const TICKS_PER_PULSE: u64 = 10;
+// How close to the middle of a transition the mouse has to be to grab it.
+const GRAB_REACH: f64 = 10.0;
+// The snapshot holding the version on disk when saving would overwrite
+// someone else's changes, for diffing against.
+const DISK_SNAPSHOT: &str = "disk";
 
 fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let subcommand = args.first().filter(|a| *a == "gen" || *a == "check");
+
+    // The config names the language, so it is read before anything can be
+    // reported. Subcommands only read the default one. A missing config file
+    // is only an error when it was asked for.
+    let config_path = match args.iter().position(|a| a == "--config") {
+        Some(i) if subcommand.is_none() => match args.get(i + 1) {
+            Some(file) => Some(std::path::PathBuf::from(file)),
+            None => {
+                eprintln!("fsmtui: --config needs a file");
+                std::process::exit(2);
+            }
+        },
+        _ => Config::default_path().filter(|p| p.exists()),
+    };
+    let config = config_path.map(|path| {
+        let config = Config::read(&path);
+        (path, config)
+    });
+
+    let language = match &config {
+        Some((_, Ok(config))) => config.language.clone(),
+        _ => None,
+    };
+    let language = language.or_else(i18n::system_language);
+    let locale_dir = Config::default_path().and_then(|p| p.parent().map(|d| d.join("locales")));
+    if let Err(e) = i18n::init(language.as_deref(), locale_dir) {
+        eprintln!("fsmtui: {e}");
+        std::process::exit(1);
+    }
+
+    match subcommand.map(String::as_str) {
+        Some("gen") => return generator::run_cli(&args[1..]),
+        Some("check") => return diagnostics::run_cli(&args[1..]),
+        _ => {}
+    }
+
+    let mut app = App::new();
+    let mut path = None;
+    let mut regex = None;
+    let mut start_tutorial = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--readonly" => app.read_only = true,
+            "--mono" => app.monochrome = true,
+            "--mouse" => app.mouse = true,
+            "--log" => {
+                let Some(file) = args.next() else {
+                    eprintln!("fsmtui: --log needs a file");
+                    std::process::exit(2);
+                };
+
+                if let Err(e) = logging::init(file) {
+                    eprintln!("fsmtui: {file}: {e}");
+                    std::process::exit(1);
+                }
+            }
+            "--no-pulse" => app.pulse = false,
+            "--tutorial" => start_tutorial = true,
+            // Already read above.
+            "--config" => {
+                args.next();
+            }
+            "--graphics" => {
+                app.graphics = graphics::supported() && graphics::cell_size().is_some();
+                if !app.graphics {
+                    eprintln!(
+                        "fsmtui: terminal graphics are not available here, using the text canvas"
+                    );
+                }
+            }
+            "--present" => {
+                let Some(script) = args.next() else {
+                    eprintln!("fsmtui: --present needs a script file");
+                    std::process::exit(2);
+                };
+
+                match std::fs::read_to_string(script) {
+                    Ok(script) => app.start_presentation(&script),
+                    Err(e) => {
+                        eprintln!("fsmtui: {script}: {e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--record" | "--replay" => {
+                let Some(file) = args.next() else {
+                    eprintln!("fsmtui: {arg} needs a file");
+                    std::process::exit(2);
+                };
+
+                let result = if arg == "--record" {
+                    Recorder::create(file).map(|r| app.recorder = Some(r))
+                } else {
+                    Replay::load(file).map(|r| app.replay = Some(r))
+                };
+
+                if let Err(e) = result {
+                    eprintln!("fsmtui: {file}: {e}");
+                    std::process::exit(1);
+                }
+            }
+            "--regex" => {
+                let Some(pattern) = args.next() else {
+                    eprintln!("fsmtui: --regex needs a pattern");
+                    std::process::exit(2);
+                };
+                regex = Some(pattern);
+            }
+            "--serve" => {
+                // A bare port is only served to this computer.
+                let address = args.next().and_then(|a| {
+                    a.parse::<u16>()
+                        .map(|port| std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+                        .or_else(|_| a.parse())
+                        .ok()
+                });
+                let Some(address) = address else {
+                    eprintln!(
+                        "fsmtui: --serve needs a port number or an address like 0.0.0.0:8000"
+                    );
+                    std::process::exit(2);
+                };
+
+                match WebView::serve(address) {
+                    Ok(web_view) => app.web_view = Some(web_view),
+                    Err(e) => {
+                        eprintln!("fsmtui: cannot serve on {address}: {e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => {
+                eprintln!(
+                    "usage: fsmtui [--config FILE] [--log FILE] [--readonly] [--mono] [--mouse] [--no-pulse] [--graphics] [--present SCRIPT] [--record FILE] [--replay FILE] [--serve [ADDRESS:]PORT] [--tutorial] [--regex PATTERN | FILE]"
+                );
+                std::process::exit(2);
+            }
+        }
+    }
+
+    if let Some((config_path, config)) = config {
+        match config.and_then(|config| config.validate().map(|()| config)) {
+            Ok(config) => {
+                app.pulse &= config.pulse;
+                let profile = config.profile.as_ref().map(|profile| {
+                    config_path
+                        .parent()
+                        .map_or(profile.clone(), |dir| dir.join(profile))
+                });
+                app.config = config;
+
+                if let Some(profile) = profile {
+                    let applied = profile::load(&profile)
+                        .map_err(|e| e.to_string())
+                        .and_then(|p| app.apply_profile(p));
+                    if let Err(e) = applied {
+                        eprintln!("fsmtui: {}: {e}", profile.display());
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("fsmtui: {}: {e}", config_path.display());
+                std::process::exit(1);
+            }
+        }
+        app.reset_view();
+    }
+
+    if regex.is_some() && path.is_some() {
+        eprintln!("fsmtui: --regex cannot be combined with a file, save with S instead");
+        std::process::exit(2);
+    }
+
+    if let Some(pattern) = regex {
+        match import::regex(pattern) {
+            Ok(machine) => app.set_machine(machine),
+            Err(e) => {
+                eprintln!("fsmtui: {pattern}: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Offered by itself only to someone starting with an empty machine.
+    if start_tutorial || (path.is_none() && regex.is_none() && tutorial::first_run()) {
+        app.tutorial = Some(tutorial::Tutorial::new());
+    }
+
+    if let Some(path) = path {
+        if let Some((project, None)) = project::split(path.as_ref()) {
+            app.file_browser = Some(FileBrowser::in_project(file_browser::Mode::Open, project));
+        } else {
+            match storage::backend(path.as_ref()).and_then(|backend| backend.load()) {
+                Ok(machine) => {
+                    app.set_machine(machine);
+                    app.load_snapshots(path.as_ref());
+                    app.disk_fingerprint = storage::fingerprint(path.as_ref());
+                    app.file_path = Some(path.into());
+                }
+                Err(e) => {
+                    eprintln!("fsmtui: {path}: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    let conflicts = app.keymap.conflicts();
+    if !conflicts.is_empty() {
+        app.report(t!("error.conflicts", conflicts = conflicts.join("; ")));
+    }
+
     let terminal = ratatui::init();
-    let app_result = App::new().run(terminal);
+    emergency::install()?;
+    // Pasted text arrives as a single event instead of one key per character.
+    execute!(std::io::stdout(), EnableBracketedPaste)?;
+    let mouse = app.mouse;
+    if mouse {
+        execute!(std::io::stdout(), EnableMouseCapture)?;
+    }
+    let app_result = app.run(terminal);
+    if mouse {
+        execute!(std::io::stdout(), DisableMouseCapture)?;
+    }
+    execute!(std::io::stdout(), DisableBracketedPaste)?;
     ratatui::restore();
     app_result
 }
 
+// Picks the reader by file extension; anything unknown is treated as a JSON
+// machine file.
+fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Machine> {
+    let path = path.as_ref();
+
+    match import::reader(path) {
+        Some(read) => read(&std::fs::read_to_string(path)?),
+        None => machine_file::load(path),
+    }
+}
+
+// States that only route transitions, drawn as diamonds. A choice picks
+// one of its outgoing transitions dynamically, while a junction chains
+// transitions statically.
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Pseudo {
+    Choice,
+    Junction,
+}
+
 struct FSMState {
     x: f64,
     y: f64,
     name: String,
+    accepting: bool,
+    pseudo: Option<Pseudo>,
     next_states: Vec<Weak<RefCell<FSMState>>>,
+    // Transitions bowed into a curve, with the offset of the curve's control
+    // point from the middle of the straight line. Entries for transitions
+    // that no longer exist are ignored.
+    bends: Vec<(Weak<RefCell<FSMState>>, Vector2D)>,
+    // Free text explaining transitions, kept the same way as bends.
+    notes: Vec<(Weak<RefCell<FSMState>>, String)>,
 }
 
 impl FSMState {
-    fn circle_radius(&self) -> f64 {
-        ((self.name.len() * 2) as f64 + 5.0).max(10.0)
+    fn new(name: String, x: f64, y: f64) -> Self {
+        Self {
+            x,
+            y,
+            name,
+            accepting: false,
+            pseudo: None,
+            next_states: vec![],
+            bends: vec![],
+            notes: vec![],
+        }
+    }
+
+    fn note(&self, to: *const RefCell<FSMState>) -> Option<&str> {
+        self.notes
+            .iter()
+            .find(|(state, _)| std::ptr::eq(state.as_ptr(), to))
+            .map(|(_, note)| note.as_str())
     }
 
-    fn to_circle(&self, color: Color) -> Circle {
-        Circle {
+    fn set_note(&mut self, to: &Rc<RefCell<FSMState>>, note: Option<String>) {
+        self.notes.retain(|(state, _)| {
+            state.strong_count() > 0 && !std::ptr::eq(state.as_ptr(), Rc::as_ptr(to))
+        });
+        if let Some(note) = note {
+            self.notes.push((Rc::downgrade(to), note));
+        }
+    }
+
+    fn bend(&self, to: *const RefCell<FSMState>) -> Option<Vector2D> {
+        self.bends
+            .iter()
+            .find(|(state, _)| std::ptr::eq(state.as_ptr(), to))
+            .map(|(_, bend)| *bend)
+    }
+
+    // A bend of `None` makes the transition straight again.
+    fn set_bend(&mut self, to: &Rc<RefCell<FSMState>>, bend: Option<Vector2D>) {
+        self.bends.retain(|(state, _)| {
+            state.strong_count() > 0 && !std::ptr::eq(state.as_ptr(), Rc::as_ptr(to))
+        });
+        if let Some(bend) = bend {
+            self.bends.push((Rc::downgrade(to), bend));
+        }
+    }
+
+    fn position(&self) -> Vector2D {
+        Vector2D {
             x: self.x,
             y: self.y,
-            radius: self.circle_radius(),
-            color,
         }
     }
 
-    fn draw(&self, circle_color: Color, canvas_ctx: &mut Context<'_>) {
+    // Display width in terminal cells, so wide CJK characters and emoji count
+    // double and combining marks don't count at all.
+    fn label_width(&self) -> usize {
+        self.name.width()
+    }
+
+    fn circle_radius(&self, style: &config::StateStyle) -> f64 {
+        match (style.size, style.label) {
+            (config::StateSize::Grow, config::LabelPosition::Inside) => {
+                ((self.label_width() * 2) as f64 + 5.0).max(10.0)
+            }
+            _ => style.radius,
+        }
+    }
+
+    // The name as drawn, cut short with an ellipsis when the circle doesn't
+    // grow to fit it.
+    fn label(&self, style: &config::StateStyle) -> String {
+        // The inverse of the growing radius, so a name that would fit a
+        // circle of this size is shown whole.
+        let room = ((style.radius - 5.0) / 2.0).max(1.0) as usize;
+        if style.size == config::StateSize::Grow
+            || style.label != config::LabelPosition::Inside
+            || self.label_width() <= room
+        {
+            return self.name.clone();
+        }
+
+        let mut label = String::new();
+        let mut width = 0;
+        for ch in self.name.chars() {
+            width += ch.width().unwrap_or(0);
+            if width >= room {
+                break;
+            }
+            label.push(ch);
+        }
+        label.push('…');
+        label
+    }
+
+    // Returns the number of shapes drawn, for the performance overlay.
+    fn draw(
+        &self,
+        circle_color: Color,
+        dashed: bool,
+        // The color of the edge to a given state, and whether to draw it
+        // doubled, or nothing to leave the edge out.
+        edge_style: impl Fn(&FSMState) -> Option<(Color, bool)>,
+        edge_styles: &config::EdgeStyles,
+        state_style: &config::StateStyle,
+        surface: &mut impl Surface,
+    ) -> usize {
+        let mut draw_calls = 0;
+        let radius = self.circle_radius(state_style);
+
         for next_state in &self.next_states {
             if let Some(state) = next_state.upgrade() {
+                // A straight arrow from a state to itself has no direction.
+                if std::ptr::eq(state.as_ptr(), self) {
+                    let Some((color, _)) = edge_style(self) else {
+                        continue;
+                    };
+                    draw_calls +=
+                        self.draw_self_loop(radius, color, &edge_styles.self_loop, surface);
+                    continue;
+                }
+
+                let bend = self.bend(Rc::as_ptr(&state));
                 let state = state.borrow();
+                let Some((color, doubled)) = edge_style(&state) else {
+                    continue;
+                };
+
+                if let Some(bend) = bend {
+                    let control = (self.position() + state.position()) * 0.5 + bend;
+                    let v1 =
+                        self.position() + (control - self.position()).normalized() * 1.5 * radius;
+                    let v2 = state.position()
+                        - (state.position() - control).normalized()
+                            * 1.5
+                            * state.circle_radius(state_style);
+
+                    draw_calls += draw_curve(surface, v1, control, v2, color, &edge_styles.normal);
+                    if doubled {
+                        let offset = (v2 - v1).normalized().rotate(f64::consts::FRAC_PI_2) * 2.0;
+                        draw_calls += draw_curve(
+                            surface,
+                            v1 + offset,
+                            control + offset,
+                            v2 + offset,
+                            color,
+                            &config::EdgeStyle {
+                                arrowhead: config::Arrowhead::None,
+                                ..edge_styles.normal
+                            },
+                        );
+                    }
+                    continue;
+                }
 
                 let mut v1 = Vector2D {
                     x: self.x,
@@ -63,269 +513,2355 @@ impl FSMState {
                 };
 
                 let v_arrow = (v2 - v1).normalized() * 1.5;
-                v1 = v1 + v_arrow * self.circle_radius();
-                v2 = v2 - v_arrow * state.circle_radius();
-
-                let (x1, y1) = v1.into();
-                let (x2, y2) = v2.into();
-
-                canvas_ctx.draw(&Line {
-                    x1,
-                    y1,
-                    x2,
-                    y2,
-                    color: Color::White,
-                });
+                v1 = v1 + v_arrow * radius;
+                v2 = v2 - v_arrow * state.circle_radius(state_style);
+
+                draw_arrow(surface, v1, v2, color, &edge_styles.normal);
+                draw_calls += 3;
+
+                if doubled {
+                    let offset = (v2 - v1).normalized().rotate(f64::consts::FRAC_PI_2) * 2.0;
+                    surface.line(v1 + offset, v2 + offset, color);
+                    draw_calls += 1;
+                }
+            }
+        }
+        if let Some(pseudo) = self.pseudo {
+            draw_calls += draw_diamond(surface, self.position(), radius, circle_color);
+            // A junction is a smaller, doubled diamond.
+            if pseudo == Pseudo::Junction {
+                draw_calls += draw_diamond(surface, self.position(), radius * 0.5, circle_color);
+            }
+        } else if dashed {
+            draw_calls += draw_dashed_circle(surface, self.position(), radius, circle_color);
+        } else {
+            surface.circle(self.position(), radius, circle_color);
+            draw_calls += 1;
+        }
+        if self.accepting && self.pseudo.is_none() {
+            surface.circle(self.position(), radius - 3.0, circle_color);
+            draw_calls += 1;
+        }
+        let label = self.label(state_style);
+        let (x, y) = match state_style.label {
+            config::LabelPosition::Inside => (self.x - label.width() as f64 + 1.0, self.y - 5.0),
+            config::LabelPosition::Below => {
+                (self.x - label.width() as f64 + 1.0, self.y - radius - 12.0)
+            }
+            config::LabelPosition::Beside => (self.x + radius + 8.0, self.y - 5.0),
+        };
+        surface.print(x, y, label.into());
 
-                // The arrowhead part
+        draw_calls + 1
+    }
 
-                let (x3, y3) =
-                    ((v1 - v2).normalized().rotate(f64::consts::FRAC_PI_4) * 10.0 + v2).into();
-                let (x4, y4) =
-                    ((v1 - v2).normalized().rotate(-f64::consts::FRAC_PI_4) * 10.0 + v2).into();
+    // A loop sitting on top of the circle, from its right side around to its
+    // left side where the arrowhead points back in.
+    fn draw_self_loop(
+        &self,
+        radius: f64,
+        color: Color,
+        style: &config::EdgeStyle,
+        surface: &mut impl Surface,
+    ) -> usize {
+        const SEGMENTS: usize = 16;
 
-                canvas_ctx.draw(&Line {
-                    x1: x2,
-                    y1: y2,
-                    x2: x3,
-                    y2: y3,
-                    color: Color::White,
-                });
+        let loop_radius = radius * 0.8;
+        let offset = radius + loop_radius * 0.3;
+        let center = self.position() + Vector2D { x: 0.0, y: offset };
 
-                canvas_ctx.draw(&Line {
-                    x1: x2,
-                    y1: y2,
-                    x2: x4,
-                    y2: y4,
-                    color: Color::White,
-                });
+        // Where the loop crosses the state's circle, as an angle around the
+        // loop's center.
+        let sin = (radius * radius - loop_radius * loop_radius - offset * offset)
+            / (2.0 * offset * loop_radius);
+        let start = sin.clamp(-1.0, 1.0).asin();
+        let end = f64::consts::PI - start;
+
+        let point = |angle: f64| {
+            center
+                + Vector2D {
+                    x: loop_radius * angle.cos(),
+                    y: loop_radius * angle.sin(),
+                }
+        };
+
+        for i in 0..SEGMENTS {
+            if style.dashed && i % 2 == 1 {
+                continue;
             }
+
+            let a = start + (end - start) * i as f64 / SEGMENTS as f64;
+            let b = start + (end - start) * (i + 1) as f64 / SEGMENTS as f64;
+            surface.line(point(a), point(b), color);
         }
-        canvas_ctx.draw(&self.to_circle(circle_color));
-        // TODO: Pass name as &str?
-        canvas_ctx.print(
-            self.x - self.name.len() as f64 + 1.0,
-            self.y - 5.0,
-            self.name.clone(),
-        );
+
+        let tangent = Vector2D {
+            x: -end.sin(),
+            y: end.cos(),
+        };
+        draw_arrowhead(surface, point(end), tangent, color, style);
+
+        SEGMENTS + 2
     }
 }
 
+// A diamond with its corners `radius` away from the center, in the shape's
+// place for pseudo-states.
+fn draw_diamond(surface: &mut impl Surface, center: Vector2D, radius: f64, color: Color) -> usize {
+    let corners = [
+        Vector2D { x: radius, y: 0.0 },
+        Vector2D { x: 0.0, y: radius },
+        Vector2D { x: -radius, y: 0.0 },
+        Vector2D { x: 0.0, y: -radius },
+    ];
+
+    for (i, corner) in corners.iter().enumerate() {
+        let next = corners[(i + 1) % corners.len()];
+        surface.line(center + *corner, center + next, color);
+    }
+    corners.len()
+}
+
+// Every other segment of a polygon approximating the circle.
+fn draw_dashed_circle(
+    surface: &mut impl Surface,
+    center: Vector2D,
+    radius: f64,
+    color: Color,
+) -> usize {
+    const SEGMENTS: usize = 16;
+
+    let point = |i: usize| {
+        let angle = std::f64::consts::TAU * i as f64 / SEGMENTS as f64;
+        center + (Vector2D { x: radius, y: 0.0 }).rotate(angle)
+    };
+
+    for i in (0..SEGMENTS).step_by(2) {
+        surface.line(point(i), point(i + 1), color);
+    }
+
+    SEGMENTS / 2
+}
+
+fn draw_arrow(
+    surface: &mut impl Surface,
+    v1: Vector2D,
+    v2: Vector2D,
+    color: Color,
+    style: &config::EdgeStyle,
+) {
+    if style.dashed {
+        const DASH: f64 = 6.0;
+        const GAP: f64 = 4.0;
+
+        let length = (v2 - v1).magnitude();
+        let direction = (v2 - v1).normalized();
+        let mut distance = 0.0;
+        while distance < length {
+            let dash_end = (distance + DASH).min(length);
+            surface.line(v1 + direction * distance, v1 + direction * dash_end, color);
+            distance = dash_end + GAP;
+        }
+    } else {
+        surface.line(v1, v2, color);
+    }
+
+    draw_arrowhead(surface, v2, v2 - v1, color, style);
+}
+
+// A quadratic Bézier curve from `v1` to `v2` pulled towards `control`, with
+// an arrowhead at `v2`. Returns the number of shapes drawn.
+fn draw_curve(
+    surface: &mut impl Surface,
+    v1: Vector2D,
+    control: Vector2D,
+    v2: Vector2D,
+    color: Color,
+    style: &config::EdgeStyle,
+) -> usize {
+    const SEGMENTS: usize = 16;
+
+    let point =
+        |t: f64| v1 * ((1.0 - t) * (1.0 - t)) + control * (2.0 * (1.0 - t) * t) + v2 * (t * t);
+
+    let mut draw_calls = 0;
+    for i in 0..SEGMENTS {
+        if style.dashed && i % 2 == 1 {
+            continue;
+        }
+
+        let a = point(i as f64 / SEGMENTS as f64);
+        let b = point((i + 1) as f64 / SEGMENTS as f64);
+        surface.line(a, b, color);
+        draw_calls += 1;
+    }
+
+    draw_arrowhead(surface, v2, v2 - control, color, style);
+    draw_calls + 2
+}
+
+// `direction` is where the arrow is heading when it reaches `tip`.
+fn draw_arrowhead(
+    surface: &mut impl Surface,
+    tip: Vector2D,
+    direction: Vector2D,
+    color: Color,
+    style: &config::EdgeStyle,
+) {
+    if style.arrowhead == config::Arrowhead::None {
+        return;
+    }
+
+    let back = (direction * -1.0).normalized() * style.arrowhead_size;
+    let v3 = back.rotate(f64::consts::FRAC_PI_4) + tip;
+    let v4 = back.rotate(-f64::consts::FRAC_PI_4) + tip;
+
+    surface.line(tip, v3, color);
+    surface.line(tip, v4, color);
+    if style.arrowhead == config::Arrowhead::Closed {
+        surface.line(v3, v4, color);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PromptKind {
+    Property,
+    Chain,
+    Command,
+}
+
+// Everything the app reacts to. The run loop turns terminal events into
+// messages, `update` applies them to the model and `view` draws it.
+enum Message {
+    Key(KeyEvent),
+    Paste(String),
+    Mouse(MouseEvent),
+    Resize,
+    Tick,
+    Written(Written),
+}
+
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+// A state being split in two, while choosing which of its incoming
+// transitions move to the copy.
+struct Split {
+    original: Weak<RefCell<FSMState>>,
+    name: String,
+    // Sources of incoming transitions, and whether each one moves.
+    incoming: Vec<(Weak<RefCell<FSMState>>, bool)>,
+    cursor: usize,
+}
+
+// A find and replace waiting for confirmation, with the changes it would
+// make.
+struct Replacement {
+    find: commands::Find,
+    with: String,
+    preview: Vec<String>,
+}
+
 struct App {
     states: std::vec::Vec<Rc<RefCell<FSMState>>>,
+    initial_state: Weak<RefCell<FSMState>>,
     selected_state: Weak<RefCell<FSMState>>,
     secondary_selected_state: Weak<RefCell<FSMState>>,
     new_state_name: Option<String>,
     marker: Marker,
+    index: SpatialIndex,
+    needs_redraw: bool,
+    exit: bool,
+    show_perf: bool,
+    frame_time: std::time::Duration,
+    // Ticks since startup, the clock for anything animated.
+    ticks: u64,
+    pulse: bool,
+    draw_calls: Cell<usize>,
+    status: Option<String>,
+    // A failure shown in a popup until the next key press, for problems the
+    // user should not miss in the status line.
+    error: Option<String>,
+    show_diagnostics: bool,
+    issue_cursor: Option<usize>,
+    properties: Vec<Property>,
+    show_properties: bool,
+    metadata: Metadata,
+    tutorial: Option<tutorial::Tutorial>,
+    show_metadata: bool,
+    property_cursor: usize,
+    history: History,
+    show_history: bool,
+    history_cursor: usize,
+    // Named copies of the machine taken with :snapshot, oldest first.
+    snapshots: Vec<(String, Snapshot)>,
+    // The snapshot the diff panel compares against.
+    diff_against: Option<String>,
+    // A line of text being typed in, for a property or a chain of states.
+    prompt: Option<Prompt>,
+    split: Option<Split>,
+    replacement: Option<Replacement>,
+    // Shows the machine as lists of states and transitions instead of the
+    // canvas, for screen readers and terminals too small to draw in.
+    list_mode: bool,
+    // Draws everything in the terminal's default color and marks states with
+    // text tags and doubled edges instead.
+    monochrome: bool,
+    // Draws the canvas shapes as a Kitty graphics image instead of with
+    // text cells.
+    graphics: bool,
+    canvas_area: Cell<Rect>,
+    config: Config,
+    // The visible part of the canvas is the configured bounds, moved to
+    // `view_center` and scaled down by `zoom`.
+    view_center: Vector2D,
+    zoom: f64,
+    mouse: bool,
+    // Where the last drag event of a pan happened.
+    pan_from: Option<(u16, u16)>,
+    // The transition whose curve is being dragged with the left button.
+    dragged_transition: Option<[Weak<RefCell<FSMState>>; 2]>,
+    highlighted_path: Vec<Weak<RefCell<FSMState>>>,
+    // While replaying the highlighted path, how many of its transitions have
+    // been walked so far.
+    path_step: Option<usize>,
+    show_depths: bool,
+    // Whether the canvas shows strongly connected components as single
+    // nodes, to keep the topology of big machines readable.
+    clustered: bool,
+    show_rulers: bool,
+    // Whether the menu lists every key instead of hints for the selection.
+    show_help: bool,
+    // Set by moving a state and cleared by the next key, to show alignment
+    // guides only while moving.
+    moving: bool,
+    // The canvas point under the mouse, for the coordinate readout.
+    cursor_point: Option<Vector2D>,
+    // The ends of the paths the canvas is narrowed down to, when only states
+    // between them are shown.
+    path_filter: Option<[Weak<RefCell<FSMState>>; 2]>,
+    file_path: Option<std::path::PathBuf>,
+    file_browser: Option<FileBrowser>,
+    // Whether the machine changed since it was last opened or saved.
+    modified: bool,
+    // Whether the machine changed since history was last recorded, so keys
+    // that edit nothing don't snapshot it.
+    edited: bool,
+    confirm_quit: bool,
+    // A save waiting for confirmation, since the file changed on disk.
+    overwrite: Option<std::path::PathBuf>,
+    // Hash of the file as it was last opened or saved here.
+    disk_fingerprint: Option<u64>,
+    quit_after_save: bool,
+    // Writes saves and exports in the background, once the app runs.
+    writer: Option<Writer>,
+    read_only: bool,
+    presenting: bool,
+    // Presentation script, one step per line.
+    script: Vec<String>,
+    script_step: Option<usize>,
+    spotlight: Vec<Weak<RefCell<FSMState>>>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    web_view: Option<WebView>,
+    // Whether the machine changed since the web view was sent it.
+    web_view_outdated: bool,
+    macros: Macros,
+    keymap: Keymap,
 }
 
 impl App {
     fn new() -> Self {
         Self {
             states: vec![],
+            initial_state: Weak::new(),
             selected_state: Weak::new(),
             secondary_selected_state: Weak::new(),
             new_state_name: None,
             marker: Marker::Braille,
+            index: SpatialIndex::new(),
+            needs_redraw: true,
+            exit: false,
+            show_perf: false,
+            frame_time: std::time::Duration::ZERO,
+            ticks: 0,
+            pulse: true,
+            draw_calls: Cell::new(0),
+            status: None,
+            error: None,
+            show_diagnostics: false,
+            issue_cursor: None,
+            properties: vec![],
+            show_properties: false,
+            metadata: Metadata::default(),
+            tutorial: None,
+            show_metadata: false,
+            property_cursor: 0,
+            history: History::new(
+                &t!("change.started"),
+                Snapshot::take(&Machine {
+                    states: vec![],
+                    initial: Weak::new(),
+                    properties: vec![],
+                    metadata: Metadata::default(),
+                }),
+            ),
+            show_history: false,
+            history_cursor: 0,
+            snapshots: vec![],
+            diff_against: None,
+            prompt: None,
+            split: None,
+            replacement: None,
+            list_mode: false,
+            graphics: false,
+            canvas_area: Cell::new(Rect::default()),
+            config: Config::default(),
+            view_center: Config::default().center(),
+            zoom: 1.0,
+            mouse: false,
+            pan_from: None,
+            dragged_transition: None,
+            monochrome: std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+            highlighted_path: vec![],
+            path_step: None,
+            show_depths: false,
+            clustered: false,
+            show_rulers: false,
+            show_help: false,
+            moving: false,
+            cursor_point: None,
+            path_filter: None,
+            file_path: None,
+            file_browser: None,
+            modified: false,
+            edited: false,
+            confirm_quit: false,
+            overwrite: None,
+            disk_fingerprint: None,
+            quit_after_save: false,
+            writer: None,
+            read_only: false,
+            presenting: false,
+            script: vec![],
+            script_step: None,
+            spotlight: vec![],
+            recorder: None,
+            replay: None,
+            web_view: None,
+            web_view_outdated: true,
+            macros: Macros::default(),
+            keymap: Keymap::default(),
         }
     }
 
-    pub fn run(mut self, mut terminal: DefaultTerminal) -> std::io::Result<()> {
-        loop {
-            terminal.draw(|frame| self.draw(frame))?;
-
-            if event::poll(std::time::Duration::from_millis(50))? {
-                if let Event::Key(key) = event::read()? {
-                    if key.kind == event::KeyEventKind::Press {
-                        if let Some(ref mut new_state_name) = self.new_state_name {
-                            match key.code {
-                                KeyCode::Char(ch) => new_state_name.push(ch),
-                                KeyCode::Backspace => {
-                                    if !new_state_name.is_empty() {
-                                        new_state_name.pop();
-                                    }
-                                }
-                                KeyCode::Enter => {
-                                    let state = Rc::new(RefCell::new(FSMState {
-                                        x: 200.0,
-                                        y: 200.0,
-                                        name: self.new_state_name.take().unwrap(),
-                                        next_states: vec![],
-                                    }));
-
-                                    self.selected_state = Rc::downgrade(&state);
-                                    self.states.push(state);
-                                }
-                                KeyCode::Esc => self.new_state_name = None,
-                                _ => (),
-                            }
+    fn set_machine(&mut self, machine: Machine) {
+        self.history = History::new(&t!("change.opened"), Snapshot::take(&machine));
+        self.history_cursor = 0;
+        self.replace_machine(machine);
+        self.property_cursor = 0;
+        self.modified = false;
+        emergency::remember(None);
+        self.selected_state = Weak::new();
+        self.secondary_selected_state = Weak::new();
+    }
 
-                            continue;
-                        }
+    // Swaps in another version of the machine, keeping the selection on
+    // states that still exist by name.
+    fn replace_machine(&mut self, machine: Machine) {
+        let name =
+            |state: &Weak<RefCell<FSMState>>| state.upgrade().map(|s| s.borrow().name.clone());
+        let (selected, secondary) = (
+            name(&self.selected_state),
+            name(&self.secondary_selected_state),
+        );
 
-                        match key.code {
-                            KeyCode::Char('q') => {
-                                return Ok(());
-                            }
-                            KeyCode::Char('s') => {
-                                self.secondary_selected_state = self.selected_state;
-                                self.selected_state = Weak::new();
-                            }
-                            KeyCode::Char('c') => {
-                                if let Some(selected_state) = self.selected_state.upgrade()
-                                    && let Some(secondary_state) =
-                                        self.secondary_selected_state.upgrade()
-                                {
-                                    let old_secondary_next_count =
-                                        secondary_state.borrow().next_states.len();
-                                    secondary_state.borrow_mut().next_states.retain(|s| {
-                                        if let Some(s2) = s.upgrade() {
-                                            !Rc::ptr_eq(&s2, &selected_state)
-                                        } else {
-                                            true
-                                        }
-                                    });
-
-                                    if old_secondary_next_count
-                                        == secondary_state.borrow().next_states.len()
-                                    {
-                                        secondary_state
-                                            .borrow_mut()
-                                            .next_states
-                                            .push(Rc::downgrade(&selected_state));
-                                    }
-
-                                    self.selected_state = Weak::new();
-                                    self.secondary_selected_state = Weak::new();
-                                }
-                            }
-                            KeyCode::Char('d') => {
-                                if let Some(state) = self.selected_state.upgrade() {
-                                    let index = self
-                                        .states
-                                        .iter()
-                                        .position(|s| Rc::ptr_eq(s, &state))
-                                        .unwrap();
-
-                                    self.states.swap_remove(index);
-                                }
-                            }
-                            KeyCode::Char('n') => self.new_state_name = Some(String::new()),
-                            KeyCode::Char('m') => {
-                                let markers = [
-                                    Marker::Dot,
-                                    Marker::Block,
-                                    Marker::Bar,
-                                    Marker::Braille,
-                                    Marker::HalfBlock,
-                                ];
-
-                                self.marker = *markers
-                                    .iter()
-                                    .skip_while(|m| **m != self.marker)
-                                    .nth(1)
-                                    .unwrap_or(&markers[0]);
-                            }
-                            KeyCode::Tab => {
-                                if let Some(state) = self.selected_state.upgrade() {
-                                    let new_selected = self
-                                        .states
-                                        .iter()
-                                        .skip_while(|s| !Rc::ptr_eq(s, &state))
-                                        .nth(1)
-                                        .unwrap_or_else(|| self.states.first().unwrap());
-
-                                    self.selected_state = Rc::downgrade(new_selected);
-                                } else if !self.states.is_empty() {
-                                    self.selected_state =
-                                        Rc::downgrade(self.states.first().unwrap());
-                                }
-                            }
-                            KeyCode::Esc => {
-                                self.selected_state = Weak::new();
-                                self.secondary_selected_state = Weak::new();
-                            }
-                            KeyCode::Left => {
-                                if let Some(selected) = self.selected_state.upgrade() {
-                                    selected.borrow_mut().x -= 5.0;
-                                }
-                            }
-                            KeyCode::Right => {
-                                if let Some(selected) = self.selected_state.upgrade() {
-                                    selected.borrow_mut().x += 5.0;
-                                }
-                            }
-                            KeyCode::Up => {
-                                if let Some(selected) = self.selected_state.upgrade() {
-                                    selected.borrow_mut().y += 5.0;
-                                }
-                            }
-                            KeyCode::Down => {
-                                if let Some(selected) = self.selected_state.upgrade() {
-                                    selected.borrow_mut().y -= 5.0;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+        self.states = machine.states;
+        self.initial_state = machine.initial;
+        self.properties = machine.properties;
+        self.metadata = machine.metadata;
+        self.property_cursor = self
+            .property_cursor
+            .min(self.properties.len().saturating_sub(1));
+        self.index.rebuild(&self.states, &self.config.states);
+        self.web_view_outdated = true;
+
+        let find = |name: Option<String>| {
+            name.and_then(|n| self.find_state(&n))
+                .map_or(Weak::new(), |s| Rc::downgrade(&s))
+        };
+        (self.selected_state, self.secondary_selected_state) = (find(selected), find(secondary));
+        self.highlighted_path.clear();
+        self.path_step = None;
+    }
+
+    fn machine(&self) -> Machine {
+        Machine {
+            states: self.states.clone(),
+            initial: self.initial_state.clone(),
+            properties: self.properties.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    fn mark_modified(&mut self) {
+        self.modified = true;
+        self.edited = true;
+        self.web_view_outdated = true;
+        self.index.transitions_changed();
+    }
+
+    // Called after each key or paste, so everything that key did becomes one
+    // entry.
+    fn record_history(&mut self) {
+        if !std::mem::take(&mut self.edited) {
+            return;
+        }
+
+        let snapshot = Arc::new(Snapshot::take(&self.machine()));
+        let file = self.file_path.clone().filter(|p| storage::is_file(p));
+        emergency::remember(Some((snapshot.clone(), file)));
+
+        if self.history.record(snapshot) {
+            tracing::info!(change = self.history.current_label(), "edited");
+        }
+    }
+
+    // Shows an error in a popup, and in the log.
+    fn report(&mut self, message: String) {
+        tracing::error!("{message}");
+        self.error = Some(message);
+    }
+
+    // Adds the snapshots kept along with a machine, as in projects.
+    fn load_snapshots(&mut self, path: &std::path::Path) {
+        match storage::backend(path).and_then(|backend| backend.snapshots()) {
+            Ok(snapshots) => {
+                for (name, snapshot) in snapshots {
+                    self.snapshots.retain(|(n, _)| *n != name);
+                    self.snapshots.push((name, snapshot));
                 }
             }
+            Err(e) => self.report(t!("error.open", path = path.display(), error = e)),
         }
     }
 
-    fn draw(&self, frame: &mut Frame) {
-        let vertical = Layout::vertical([Constraint::Percentage(70), Constraint::Percentage(30)]);
-        let [canvas, menu] = vertical.areas(frame.area());
+    fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        self.replace_machine(snapshot.restore());
+        self.mark_modified();
+    }
+
+    // Finds a spot near the default spawn point that doesn't overlap any
+    // existing state, spiraling outwards.
+    fn free_position(&self, radius: f64) -> Vector2D {
+        let [x, y] = self.config.spawn;
+        let spawn = Vector2D { x, y };
+        let step = radius * 2.0;
+
+        for ring in 0..10 {
+            let count = (ring * 6).max(1);
+            for i in 0..count {
+                let position = spawn
+                    + (Vector2D {
+                        x: step * ring as f64,
+                        y: 0.0,
+                    })
+                    .rotate(f64::consts::TAU * i as f64 / count as f64);
+
+                if self
+                    .index
+                    .query(position, radius, &self.config.states)
+                    .is_empty()
+                {
+                    return position;
+                }
+            }
+        }
+
+        spawn
+    }
+
+    // Lays states out in columns by their distance from the initial state,
+    // with unreachable states in one extra column on the right.
+    fn snap_to_layers(&mut self) {
+        let Some(initial) = self.initial_state.upgrade() else {
+            self.status = Some(t!("status.no_initial"));
+            return;
+        };
+
+        let depths = analysis::depths(&initial);
+        let unreachable_column = depths.values().max().map_or(0, |d| d + 1);
+
+        let mut columns = vec![vec![]; unreachable_column + 1];
+        for state in &self.states {
+            let depth = depths
+                .get(&Rc::as_ptr(state))
+                .copied()
+                .unwrap_or(unreachable_column);
+            columns[depth].push(state);
+        }
+
+        if columns.last().is_some_and(Vec::is_empty) {
+            columns.pop();
+        }
+
+        let ([left, right], [bottom, top]) = (self.config.x_bounds, self.config.y_bounds);
+        let width = right - left;
+        let spacing = (width * 0.88 / (columns.len().max(2) - 1) as f64).min(width * 0.16);
+        for (column, states) in columns.iter().enumerate() {
+            for (row, state) in states.iter().enumerate() {
+                let mut state = state.borrow_mut();
+                state.x = left + width * 0.06 + column as f64 * spacing;
+                state.y = bottom
+                    + (top - bottom) * (states.len() - row) as f64 / (states.len() + 1) as f64;
+            }
+        }
+
+        self.index.rebuild(&self.states, &self.config.states);
+        self.mark_modified();
+    }
+
+    fn select_relative(&mut self, offset: isize) {
+        if self.states.is_empty() {
+            return;
+        }
+
+        let count = self.states.len() as isize;
+        let current = self
+            .selected_state
+            .upgrade()
+            .and_then(|state| self.states.iter().position(|s| Rc::ptr_eq(s, &state)));
+        let index = match current {
+            Some(index) => (index as isize + offset).rem_euclid(count),
+            None if offset < 0 => count - 1,
+            None => 0,
+        };
+
+        self.selected_state = Rc::downgrade(&self.states[index as usize]);
+    }
 
-        frame.render_widget(self.canvas(), canvas);
-        frame.render_widget(
-            Paragraph::new(if self.new_state_name.is_none() {
-                "Press q to exit.
-Press tab to switch between states.
-Press Esc to unselect.
-Use the arrow keys to move states.
-Press s to select a state for connection.
-Press c to toggle connection between previously selected state to the current.
-Press n to create a new state.
-Press d to delete the selected state.
-Press m to change canvas style."
+    fn advance_tutorial(&mut self) {
+        if let Some(mut tutorial) = self.tutorial.take() {
+            if tutorial.advance(self) {
+                self.tutorial = Some(tutorial);
             } else {
-                "Creating new state.
-Type state name. Press enter to create.
-Press Esc to abort."
-            })
-            .block(Block::bordered().title("Menu")),
-            menu,
-        );
+                self.status = Some(t!("status.tutorial_done"));
+            }
+        }
     }
 
-    fn canvas(&self) -> impl Widget + '_ {
-        Canvas::default()
-            .marker(self.marker)
-            .paint(|ctx| {
-                for state in &self.states {
-                    state.borrow().draw(
-                        if let Some(selected) = self.selected_state.upgrade()
-                            && Rc::ptr_eq(state, &selected)
-                        {
-                            Color::Yellow
-                        } else if let Some(secondary_selected) =
-                            self.secondary_selected_state.upgrade()
-                            && Rc::ptr_eq(state, &secondary_selected)
-                        {
-                            Color::Cyan
-                        } else {
-                            Color::White
-                        },
-                        ctx,
-                    );
+    // Takes over the bindings and styles of a profile, for the rest of the
+    // session.
+    fn apply_profile(&mut self, profile: profile::Profile) -> Result<(), String> {
+        self.keymap = Keymap::from_entries(&profile.keys)?;
+        self.config.edges = profile.edges;
+        self.config.states = profile.states;
+        self.index.rebuild(&self.states, &self.config.states);
+        self.web_view_outdated = true;
+        Ok(())
+    }
+
+    fn move_selected(&mut self, dx: f64, dy: f64) {
+        if let Some(selected) = self.selected_state.upgrade() {
+            let from = selected.borrow().position();
+            let (mut x, mut y) = (from.x + dx, from.y + dy);
+
+            // Stops on the way wherever the state lines up with another, so
+            // alignments aren't stepped over. The next move goes on past it.
+            let between = |from: f64, to: f64, at: f64| from.min(to) < at && at < from.max(to);
+            for other in self.states.iter().filter(|s| !Rc::ptr_eq(s, &selected)) {
+                let other = other.borrow();
+                if between(from.x, x, other.x) {
+                    x = other.x;
+                }
+                if between(from.y, y, other.y) {
+                    y = other.y;
                 }
+            }
+
+            self.index.remove(&selected);
+            {
+                let mut selected = selected.borrow_mut();
+                (selected.x, selected.y) = (x, y);
+            }
+            self.index.insert(&selected, &self.config.states);
+            self.mark_modified();
+            self.moving = true;
+        }
+    }
+
+    // The transition from the connection source to the selected state, which
+    // the bend keys act on. Self loops have a shape of their own and can't be
+    // bent.
+    fn selected_transition(&mut self) -> Option<[Rc<RefCell<FSMState>>; 2]> {
+        let transition = match (
+            self.secondary_selected_state.upgrade(),
+            self.selected_state.upgrade(),
+        ) {
+            (Some(from), Some(to)) if !Rc::ptr_eq(&from, &to) => {
+                let connected = from
+                    .borrow()
+                    .next_states
+                    .iter()
+                    .any(|n| std::ptr::eq(n.as_ptr(), Rc::as_ptr(&to)));
+                connected.then_some([from, to])
+            }
+            _ => None,
+        };
+
+        if transition.is_none() {
+            self.status = Some(t!("status.no_transition"));
+        }
+        transition
+    }
+
+    fn bend_selected(&mut self, dx: f64, dy: f64) {
+        if let Some([from, to]) = self.selected_transition() {
+            let bend = from
+                .borrow()
+                .bend(Rc::as_ptr(&to))
+                .unwrap_or(Vector2D { x: 0.0, y: 0.0 });
+            from.borrow_mut()
+                .set_bend(&to, Some(bend + Vector2D { x: dx, y: dy }));
+            self.mark_modified();
+        }
+    }
+
+    pub fn run(mut self, mut terminal: DefaultTerminal) -> std::io::Result<()> {
+        let events = Events::new(TICK_RATE);
+        self.writer = Some(Writer::new(events.sender()));
+
+        while !self.exit {
+            if self.needs_redraw {
+                self.view(&mut terminal)?;
+            }
 
-                if let Some(new_state_name) = &self.new_state_name {
-                    ctx.print(0.0, 0.0, new_state_name.clone());
+            let message = match events.next()? {
+                AppEvent::Input(Event::Key(key)) if key.kind == event::KeyEventKind::Press => {
+                    Message::Key(key)
                 }
-            })
-            .x_bounds([0.0, 500.0])
-            .y_bounds([0.0, 500.0])
+                AppEvent::Input(Event::Paste(text)) => Message::Paste(text),
+                AppEvent::Input(Event::Mouse(mouse)) => Message::Mouse(mouse),
+                AppEvent::Input(Event::Resize(..)) => Message::Resize,
+                AppEvent::Input(_) => continue,
+                AppEvent::Tick => Message::Tick,
+                AppEvent::Written(written) => Message::Written(written),
+            };
+            self.update(message);
+        }
+
+        // Quitting doesn't cut off saves and exports still being written.
+        while self.writer.as_ref().is_some_and(Writer::is_busy) {
+            if let AppEvent::Written(written) = events.next()? {
+                self.update(Message::Written(written));
+            }
+        }
+
+        if self.graphics {
+            graphics::clear(&mut std::io::stdout())?;
+        }
+
+        Ok(())
+    }
+
+    // The only place the model changes: everything the app reacts to arrives
+    // here as a message.
+    fn update(&mut self, message: Message) {
+        match message {
+            Message::Key(key) => {
+                // Every key press may touch the model or the menu, so
+                // redraw after handling it.
+                self.needs_redraw = true;
+
+                if self.replay.is_some() {
+                    self.handle_replay_key(key);
+                } else {
+                    self.apply_key(key);
+                }
+            }
+            // A replay brings its own pastes and clicks.
+            Message::Paste(_) | Message::Mouse(_) if self.replay.is_some() => {}
+            Message::Paste(text) => {
+                self.needs_redraw = true;
+                self.apply_paste(text);
+            }
+            Message::Mouse(mouse) => {
+                self.needs_redraw = true;
+                self.apply_mouse(mouse);
+            }
+            Message::Resize => self.needs_redraw = true,
+            Message::Tick => self.on_tick(),
+            Message::Written(written) => {
+                self.needs_redraw = true;
+                self.finish_write(written);
+            }
+        }
+    }
+
+    // Whether the pulsing ring around selected states is showing.
+    fn pulse_on(&self) -> bool {
+        self.pulse && (self.ticks / TICKS_PER_PULSE) % 2 == 1
+    }
+
+    fn reset_view(&mut self) {
+        self.view_center = self.config.center();
+        self.zoom = 1.0;
+    }
+
+    fn x_bounds(&self) -> [f64; 2] {
+        let [min, max] = self.config.x_bounds;
+        let half = (max - min) / 2.0 / self.zoom;
+        [self.view_center.x - half, self.view_center.x + half]
+    }
+
+    fn y_bounds(&self) -> [f64; 2] {
+        let [min, max] = self.config.y_bounds;
+        let half = (max - min) / 2.0 / self.zoom;
+        [self.view_center.y - half, self.view_center.y + half]
+    }
+
+    // The canvas point under a terminal cell, if the cell is on the canvas.
+    fn canvas_point(&self, column: u16, row: u16) -> Option<Vector2D> {
+        let area = self.canvas_area.get();
+        if !area.contains((column, row).into()) {
+            return None;
+        }
+
+        let ([x0, x1], [y0, y1]) = (self.x_bounds(), self.y_bounds());
+        Some(Vector2D {
+            x: x0 + (column - area.x) as f64 / area.width as f64 * (x1 - x0),
+            y: y1 - (row - area.y) as f64 / area.height as f64 * (y1 - y0),
+        })
+    }
+
+    // Scrolling zooms around the point under the cursor, dragging with the
+    // middle or right button pans, clicking a state selects it, and dragging
+    // the middle of a transition with the left button bends it.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let (column, row) = (mouse.column, mouse.row);
+        self.cursor_point = self.canvas_point(column, row);
+
+        match mouse.kind {
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let Some(anchor) = self.canvas_point(column, row) else {
+                    return;
+                };
+
+                let factor = if mouse.kind == MouseEventKind::ScrollUp {
+                    1.25
+                } else {
+                    0.8
+                };
+                let zoom = (self.zoom * factor).clamp(0.1, 20.0);
+
+                self.view_center = anchor + (self.view_center - anchor) * (self.zoom / zoom);
+                self.zoom = zoom;
+            }
+            MouseEventKind::Down(MouseButton::Middle | MouseButton::Right) => {
+                self.pan_from = self.canvas_point(column, row).map(|_| (column, row));
+            }
+            MouseEventKind::Drag(MouseButton::Middle | MouseButton::Right) => {
+                let Some((from_column, from_row)) = self.pan_from else {
+                    return;
+                };
+
+                let area = self.canvas_area.get();
+                let ([x0, x1], [y0, y1]) = (self.x_bounds(), self.y_bounds());
+                self.view_center = self.view_center
+                    - Vector2D {
+                        x: (column as f64 - from_column as f64) / area.width as f64 * (x1 - x0),
+                        y: (from_row as f64 - row as f64) / area.height as f64 * (y1 - y0),
+                    };
+                self.pan_from = Some((column, row));
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let Some(point) = self.canvas_point(column, row) else {
+                    return;
+                };
+
+                if let Some(state) = self.index.state_at(point, &self.config.states) {
+                    self.selected_state = Rc::downgrade(&state);
+                } else if !self.read_only {
+                    self.dragged_transition = self.index.transition_at(point, GRAB_REACH);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some((from, to)) = self
+                    .dragged_transition
+                    .as_ref()
+                    .and_then(|[from, to]| Some((from.upgrade()?, to.upgrade()?)))
+                else {
+                    return;
+                };
+                let Some(point) = self.canvas_point(column, row) else {
+                    return;
+                };
+
+                let middle = (from.borrow().position() + to.borrow().position()) * 0.5;
+                from.borrow_mut()
+                    .set_bend(&to, Some((point - middle) * 2.0));
+                self.mark_modified();
+            }
+            MouseEventKind::Up(_) => {
+                self.pan_from = None;
+                self.dragged_transition = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_key(&mut self, key: KeyEvent) {
+        self.moving = false;
+        self.handle_key(key);
+        self.record_history();
+        self.advance_tutorial();
+        self.record(Event::Key(key));
+    }
+
+    fn apply_paste(&mut self, text: String) {
+        self.handle_paste(&text);
+        self.record_history();
+        self.record(Event::Paste(text));
+    }
+
+    fn apply_mouse(&mut self, mouse: MouseEvent) {
+        let bending = self.dragged_transition.is_some();
+        self.handle_mouse(mouse);
+        if bending {
+            self.record_history();
+        }
+        self.record(Event::Mouse(mouse));
+    }
+
+    fn record(&mut self, event: Event) {
+        let recorded = self.recorder.as_mut().map(|r| r.record(&event));
+        if let Some(Err(e)) = recorded {
+            self.report(t!("error.recording", error = e));
+            self.recorder = None;
+        }
+    }
+
+    fn replay_next(&mut self) {
+        match self.replay.as_mut().and_then(Replay::next) {
+            Some(Event::Key(key)) => self.apply_key(key),
+            Some(Event::Paste(text)) => self.apply_paste(text),
+            Some(Event::Mouse(mouse)) => self.apply_mouse(mouse),
+            Some(_) => {}
+            None => {
+                self.replay = None;
+                self.status = Some(t!("status.replay_finished"));
+            }
+        }
+        self.needs_redraw = true;
+    }
+
+    // While a recording is replayed the keyboard only controls the playback.
+    fn handle_replay_key(&mut self, key: KeyEvent) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Char(' ') => replay.paused = !replay.paused,
+            KeyCode::Char('.') if replay.paused => self.replay_next(),
+            KeyCode::Esc => {
+                self.replay = None;
+                self.status = Some(t!("status.replay_stopped"));
+            }
+            _ => {}
+        }
+    }
+
+    fn on_tick(&mut self) {
+        self.ticks += 1;
+        let selection = self.selected_state.strong_count() > 0
+            || self.secondary_selected_state.strong_count() > 0;
+        if self.pulse && selection && self.ticks % TICKS_PER_PULSE == 0 {
+            self.needs_redraw = true;
+        }
+
+        if self.ticks % TICKS_PER_STEP != 0 {
+            return;
+        }
+
+        if self.replay.as_ref().is_some_and(|r| !r.paused) {
+            self.replay_next();
+        }
+
+        if let Some(step) = self.path_step {
+            self.path_step = (step + 2 < self.highlighted_path.len()).then_some(step + 1);
+            self.needs_redraw = true;
+        }
+    }
+
+    fn open_file(&mut self, path: std::path::PathBuf) {
+        if let Some((project, None)) = project::split(&path) {
+            self.file_browser = Some(FileBrowser::in_project(file_browser::Mode::Open, project));
+            return;
+        }
+
+        match storage::backend(&path).and_then(|backend| backend.load()) {
+            Ok(machine) => {
+                tracing::info!(path = %path.display(), states = machine.states.len(), "opened");
+                self.set_machine(machine);
+                self.load_snapshots(&path);
+                self.status = Some(t!("status.opened", path = path.display()));
+                self.disk_fingerprint = storage::fingerprint(&path);
+                self.file_path = Some(path);
+            }
+            Err(e) => self.report(t!("error.open", path = path.display(), error = e)),
+        }
+    }
+
+    // Saves, unless the file was changed by something else since it was
+    // opened or last saved here. Then asks first what to do.
+    fn save(&mut self, path: std::path::PathBuf) {
+        // A save still being written changes the file itself.
+        let writing = self.writer.as_ref().is_some_and(Writer::is_busy);
+        let changed_on_disk = !writing
+            && self.file_path.as_ref() == Some(&path)
+            && storage::fingerprint(&path).is_some_and(|f| Some(f) != self.disk_fingerprint);
+        if changed_on_disk {
+            self.overwrite = Some(path);
+            return;
+        }
+
+        self.write(path);
+    }
+
+    fn write(&mut self, path: std::path::PathBuf) {
+        let snapshots = self
+            .snapshots
+            .iter()
+            .filter(|(name, _)| name != DISK_SNAPSHOT)
+            .cloned()
+            .collect();
+        self.status = Some(t!("status.saving", path = path.display()));
+        self.start_write(Job::Save {
+            path,
+            machine: Snapshot::take(&self.machine()),
+            snapshots,
+        });
+    }
+
+    fn start_write(&mut self, job: Job) {
+        self.writer
+            .as_mut()
+            .expect("writes start from messages, which come once the app runs")
+            .start(job);
+    }
+
+    fn finish_write(&mut self, written: Written) {
+        if let Some(writer) = &mut self.writer {
+            writer.finished();
+        }
+
+        match written {
+            Written::Saved {
+                path,
+                machine,
+                result: Ok(fingerprint),
+            } => {
+                tracing::info!(path = %path.display(), states = self.states.len(), "saved");
+                self.status = Some(t!("status.saved", path = path.display()));
+                self.disk_fingerprint = fingerprint;
+                self.file_path = Some(path);
+                // Edits made while saving still need saving.
+                self.modified = machine != Snapshot::take(&self.machine());
+                if !self.modified {
+                    emergency::remember(None);
+                }
+                self.exit = self.quit_after_save;
+            }
+            Written::Saved {
+                path,
+                result: Err(e),
+                ..
+            } => {
+                self.report(t!("error.save", path = path.display(), error = e));
+            }
+            Written::Exported { path, result } => {
+                self.status = Some(match result {
+                    Ok(()) => t!("status.exported", path = path.display()),
+                    Err(e) => t!("status.export_failed", path = path.display(), error = e),
+                });
+            }
+        }
+    }
+
+    fn save_or_save_as(&mut self) {
+        // Imported diagrams are saved as a new machine file.
+        match self.file_path.clone() {
+            Some(path) if import::reader(&path).is_none() => self.save(path),
+            _ => self.open_file_browser(file_browser::Mode::SaveAs),
+        }
+    }
+
+    fn open_file_browser(&mut self, mode: file_browser::Mode) {
+        if let Some((project, _)) = self.file_path.as_deref().and_then(project::split) {
+            self.file_browser = Some(FileBrowser::in_project(mode, project));
+            return;
+        }
+
+        let dir = self
+            .file_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| p.to_path_buf())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+
+        self.file_browser = Some(FileBrowser::new(mode, dir));
+    }
+
+    fn handle_file_browser_key(&mut self, key: KeyEvent) {
+        let Some(browser) = &mut self.file_browser else {
+            return;
+        };
+
+        let mode = browser.mode;
+        match browser.handle_key(key) {
+            file_browser::Outcome::Pending => return,
+            file_browser::Outcome::Cancelled => self.quit_after_save = false,
+            file_browser::Outcome::Chosen(path) => match mode {
+                file_browser::Mode::Open => self.open_file(path),
+                file_browser::Mode::SaveAs => self.save(path),
+            },
+        }
+
+        self.file_browser = None;
+    }
+
+    fn find_state(&self, name: &str) -> Option<Rc<RefCell<FSMState>>> {
+        self.states
+            .iter()
+            .find(|s| s.borrow().name == name)
+            .cloned()
+    }
+
+    // Blank lines and lines starting with # are skipped.
+    fn start_presentation(&mut self, script: &str) {
+        self.script = script
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        self.script_step = None;
+        self.presenting = true;
+    }
+
+    // A step is either a chain like `A -> B -> C`, highlighting that path, or
+    // a comma separated list of states to highlight.
+    fn show_script_step(&mut self, step: usize) {
+        let line = &self.script[step];
+        let (separator, is_path) = if line.contains("->") {
+            ("->", true)
+        } else {
+            (",", false)
+        };
+
+        let states: Vec<_> = line
+            .split(separator)
+            .filter_map(|name| self.find_state(name.trim()))
+            .map(|s| Rc::downgrade(&s))
+            .collect();
+
+        if is_path {
+            self.highlighted_path = states;
+            self.spotlight.clear();
+        } else {
+            self.spotlight = states;
+            self.highlighted_path.clear();
+        }
+
+        self.path_step = None;
+        self.script_step = Some(step);
+    }
+
+    fn handle_presentation_key(&mut self, key: KeyEvent) {
+        let last = self.script.len().checked_sub(1);
+
+        match key.code {
+            KeyCode::Char(' ') | KeyCode::Right | KeyCode::PageDown => {
+                let next = self.script_step.map_or(0, |s| s + 1);
+                if last.is_some_and(|last| next <= last) {
+                    self.show_script_step(next);
+                }
+            }
+            KeyCode::Left | KeyCode::Backspace | KeyCode::PageUp => {
+                if let Some(step) = self.script_step.filter(|s| *s > 0) {
+                    self.show_script_step(step - 1);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('F') => {
+                self.presenting = false;
+                self.script_step = None;
+                self.spotlight.clear();
+                self.highlighted_path.clear();
+                self.path_step = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            input: String::new(),
+        });
+    }
+
+    fn submit_prompt(&mut self, prompt: Prompt) {
+        let input = prompt.input;
+
+        match prompt.kind {
+            PromptKind::Property => match Property::parse(&input) {
+                Some(property) => {
+                    self.properties.push(property);
+                    self.property_cursor = self.properties.len() - 1;
+                    self.mark_modified();
+                }
+                None => {
+                    self.status = Some(t!(
+                        "status.bad_property",
+                        input = format!("{input:?}"),
+                        syntax = properties::syntax()
+                    ))
+                }
+            },
+            PromptKind::Chain => self.capture_chain(&input),
+            PromptKind::Command => match commands::parse(&input) {
+                Ok(command) => self.run_command(command),
+                Err(e) => {
+                    self.status = Some(t!(
+                        "status.bad_command",
+                        error = e,
+                        commands = t!("command.list")
+                    ))
+                }
+            },
+        }
+    }
+
+    fn run_command(&mut self, command: commands::Command) {
+        use commands::Command;
+
+        let edits = matches!(
+            command,
+            Command::New(_)
+                | Command::Rename { .. }
+                | Command::Delete(_)
+                | Command::Restore(_)
+                | Command::Note(_)
+                | Command::Merge
+                | Command::Split(_)
+                | Command::Insert(_)
+                | Command::Replace { .. }
+                | Command::Title(_)
+                | Command::Author(_)
+                | Command::Description(_)
+        );
+        if self.read_only && edits {
+            self.status = Some(t!("status.read_only"));
+            return;
+        }
+
+        match command {
+            Command::Write(Some(path)) => self.save(path),
+            Command::Write(None) => self.save_or_save_as(),
+            Command::WriteQuit => {
+                self.quit_after_save = true;
+                self.save_or_save_as();
+            }
+            Command::Quit { force } => {
+                if self.modified && !force {
+                    self.status = Some(t!("status.unsaved_quit"));
+                } else {
+                    self.exit = true;
+                }
+            }
+            Command::Edit { path, force } => {
+                if self.modified && !force {
+                    self.status = Some(t!("status.unsaved_edit"));
+                    return;
+                }
+
+                self.open_file(path);
+            }
+            Command::New(name) => {
+                self.create_state(name);
+            }
+            Command::Rename { from, to } => {
+                let Some(state) = self.find_state(&from) else {
+                    self.status = Some(t!("status.no_such_state", name = from));
+                    return;
+                };
+                if self.find_state(&to).is_some() {
+                    self.status = Some(t!("status.state_exists", name = to));
+                    return;
+                }
+
+                state.borrow_mut().name = to;
+                // The circle grows or shrinks with the name.
+                self.index.rebuild(&self.states, &self.config.states);
+                self.mark_modified();
+            }
+            Command::Delete(pattern) => {
+                let doomed: Vec<_> = self
+                    .states
+                    .iter()
+                    .filter(|s| commands::matches(&pattern, &s.borrow().name))
+                    .cloned()
+                    .collect();
+
+                self.status = Some(t!("status.deleted", count = doomed.len()));
+                if !doomed.is_empty() {
+                    self.remove_states(&doomed);
+                    self.mark_modified();
+                }
+            }
+            Command::Snapshot(name) => {
+                self.snapshots.retain(|(n, _)| *n != name);
+                let snapshot = Snapshot::take(&self.machine());
+                self.status = Some(t!("status.snapshot_taken", name = name));
+                // Backends that keep snapshots get it right away, as taking
+                // one doesn't count as an unsaved change.
+                if let Some(path) = &self.file_path {
+                    let kept = storage::backend(path)
+                        .and_then(|backend| backend.save_snapshot(&name, &snapshot));
+                    match kept {
+                        Ok(()) => (),
+                        // The machine isn't in the project yet. Saving it
+                        // stores its snapshots too.
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                            self.status = Some(t!("status.snapshot_unsaved", name = name));
+                        }
+                        Err(e) => self.report(t!("error.snapshot", name = name, error = e)),
+                    }
+                }
+                self.snapshots.push((name, snapshot));
+            }
+            Command::Restore(name) => match self.find_snapshot(&name) {
+                Some(snapshot) => {
+                    self.restore_snapshot(snapshot.clone());
+                    tracing::info!(snapshot = name, "restored");
+                    self.status = Some(t!("status.snapshot_restored", name = name));
+                }
+                None => self.status = Some(t!("status.no_such_snapshot", name = name)),
+            },
+            Command::Diff(None) => self.diff_against = None,
+            Command::Diff(Some(name)) => match self.find_snapshot(&name) {
+                Some(_) => self.diff_against = Some(name),
+                None => self.status = Some(t!("status.no_such_snapshot", name = name)),
+            },
+            Command::Note(note) => {
+                if let Some([from, to]) = self.selected_transition() {
+                    self.status = Some(match note {
+                        Some(_) => t!("status.noted"),
+                        None => t!("status.note_removed"),
+                    });
+                    from.borrow_mut().set_note(&to, note);
+                    self.mark_modified();
+                }
+            }
+            Command::Merge => match (
+                self.selected_state.upgrade(),
+                self.secondary_selected_state.upgrade(),
+            ) {
+                (Some(kept), Some(gone)) if !Rc::ptr_eq(&kept, &gone) => {
+                    self.status = Some(t!(
+                        "status.merged",
+                        gone = gone.borrow().name,
+                        kept = kept.borrow().name
+                    ));
+                    self.merge_states(&kept, &gone);
+                    self.secondary_selected_state = Weak::new();
+                    self.mark_modified();
+                }
+                _ => self.status = Some(t!("status.select_merge")),
+            },
+            Command::Split(name) => {
+                let Some(original) = self.selected_state.upgrade() else {
+                    self.status = Some(t!("status.select_split"));
+                    return;
+                };
+                if self.find_state(&name).is_some() {
+                    self.status = Some(t!("status.state_exists", name = name));
+                    return;
+                }
+
+                let incoming = self
+                    .states
+                    .iter()
+                    .filter(|s| {
+                        s.borrow()
+                            .next_states
+                            .iter()
+                            .any(|n| std::ptr::eq(n.as_ptr(), Rc::as_ptr(&original)))
+                    })
+                    .map(|s| (Rc::downgrade(s), false))
+                    .collect();
+                self.split = Some(Split {
+                    original: Rc::downgrade(&original),
+                    name,
+                    incoming,
+                    cursor: 0,
+                });
+            }
+            Command::Insert(name) => {
+                if self.find_state(&name).is_some() {
+                    self.status = Some(t!("status.state_exists", name = name));
+                    return;
+                }
+                if let Some([from, to]) = self.selected_transition() {
+                    self.insert_state(&from, &to, name);
+                    self.secondary_selected_state = Weak::new();
+                }
+            }
+            Command::Replace { find, with } => {
+                if find.is_empty() {
+                    self.status = Some(t!("status.empty_find"));
+                    return;
+                }
+
+                // Two states can't end up with the same name.
+                let mut names: Vec<String> = self
+                    .states
+                    .iter()
+                    .map(|s| find.replace(&s.borrow().name, &with))
+                    .collect();
+                names.sort();
+                if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+                    self.status = Some(t!("status.replace_clash", name = pair[0]));
+                    return;
+                }
+
+                let mut preview = vec![];
+                for state in &self.states {
+                    let state = state.borrow();
+                    if find.is_match(&state.name) {
+                        preview.push(t!(
+                            "panel.replace.state",
+                            from = state.name,
+                            to = find.replace(&state.name, &with)
+                        ));
+                    }
+                    for (to, note) in &state.notes {
+                        let Some(to) = to.upgrade().filter(|_| find.is_match(note)) else {
+                            continue;
+                        };
+                        preview.push(t!(
+                            "panel.replace.note",
+                            from = state.name,
+                            to = to.borrow().name,
+                            text = find.replace(note, &with)
+                        ));
+                    }
+                }
+
+                if preview.is_empty() {
+                    self.status = Some(t!("status.not_found", find = find));
+                } else {
+                    self.replacement = Some(Replacement {
+                        find,
+                        with,
+                        preview,
+                    });
+                }
+            }
+            Command::Title(text) => {
+                self.metadata.title = text;
+                self.show_metadata = true;
+                self.mark_modified();
+            }
+            Command::Author(text) => {
+                self.metadata.author = text;
+                self.show_metadata = true;
+                self.mark_modified();
+            }
+            Command::Description(text) => {
+                self.metadata.description = text;
+                self.show_metadata = true;
+                self.mark_modified();
+            }
+            Command::Tutorial => {
+                if self.tutorial.take().is_some() {
+                    self.status = Some(t!("status.tutorial_left"));
+                } else {
+                    self.tutorial = Some(tutorial::Tutorial::new());
+                }
+            }
+            Command::Export { path, legend } => {
+                let text = match export::Format::from_path(&path) {
+                    Some(export::Format::Svg) => {
+                        let bounds = (self.config.x_bounds, self.config.y_bounds);
+                        let svg = export::svg(
+                            &self.states,
+                            &self.initial_state,
+                            bounds,
+                            &self.config.states,
+                            &self.config.edges,
+                            &self.metadata,
+                        );
+                        match legend {
+                            true => export::add_legend(
+                                &svg,
+                                &export::svg_legend(&self.states, &self.initial_state, bounds),
+                            ),
+                            false => svg,
+                        }
+                    }
+                    Some(export::Format::Dot) => {
+                        export::dot(&self.states, &self.initial_state, &self.metadata, legend)
+                    }
+                    None => {
+                        self.status = Some(t!("status.export_format"));
+                        return;
+                    }
+                };
+                self.status = Some(t!("status.exporting", path = path.display()));
+                self.start_write(Job::Export {
+                    path,
+                    contents: text,
+                });
+            }
+            Command::ExportProfile(path) => {
+                let profile = profile::Profile {
+                    keys: self.keymap.entries(),
+                    edges: self.config.edges,
+                    states: self.config.states,
+                };
+                self.status = Some(match profile::save(&path, &profile) {
+                    Ok(()) => t!("status.profile_exported", path = path.display()),
+                    Err(e) => t!("status.profile_failed", path = path.display(), error = e),
+                });
+            }
+            Command::ImportProfile(path) => {
+                let applied = profile::load(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|p| self.apply_profile(p));
+                self.status = Some(match applied {
+                    Ok(()) => t!("status.profile_imported", path = path.display()),
+                    Err(e) => t!("status.profile_failed", path = path.display(), error = e),
+                });
+
+                let conflicts = self.keymap.conflicts();
+                if !conflicts.is_empty() {
+                    self.report(t!("error.conflicts", conflicts = conflicts.join("; ")));
+                }
+            }
+            Command::Snapshots => {
+                let names: Vec<&str> = self.snapshots.iter().map(|(n, _)| n.as_str()).collect();
+                self.status = Some(match names.is_empty() {
+                    true => t!("status.no_snapshots"),
+                    false => t!("status.snapshots", names = names.join(", ")),
+                });
+            }
+        }
+    }
+
+    // Creates the states of a chain like `Idle -> Running : start -> Done`
+    // that don't exist yet, and connects each to the next. Labels after a
+    // colon are accepted but dropped, since transitions are unlabeled.
+    fn capture_chain(&mut self, chain: &str) {
+        let names: Vec<&str> = chain
+            .split("->")
+            .map(|part| part.split_once(':').map_or(part, |(name, _)| name).trim())
+            .collect();
+
+        if names.iter().any(|name| name.is_empty()) {
+            self.status = Some(t!("status.bad_chain", chain = format!("{chain:?}")));
+            return;
+        }
+
+        let mut created = 0;
+        let states: Vec<_> = names
+            .iter()
+            .map(|name| {
+                self.find_state(name).unwrap_or_else(|| {
+                    created += 1;
+                    self.create_state(name.to_string())
+                })
+            })
+            .collect();
+
+        let mut connected = 0;
+        for pair in states.windows(2) {
+            let mut from = pair[0].borrow_mut();
+            if !from
+                .next_states
+                .iter()
+                .any(|n| std::ptr::eq(n.as_ptr(), Rc::as_ptr(&pair[1])))
+            {
+                from.next_states.push(Rc::downgrade(&pair[1]));
+                connected += 1;
+            }
+        }
+
+        self.mark_modified();
+        self.status = Some(t!(
+            "status.chain_captured",
+            states = created,
+            transitions = connected
+        ));
+    }
+
+    fn create_state(&mut self, name: String) -> Rc<RefCell<FSMState>> {
+        let mut state = FSMState::new(name, 0.0, 0.0);
+        (state.x, state.y) = self
+            .free_position(state.circle_radius(&self.config.states))
+            .into();
+
+        let state = Rc::new(RefCell::new(state));
+
+        self.index.insert(&state, &self.config.states);
+        self.selected_state = Rc::downgrade(&state);
+        self.states.push(state.clone());
+        self.mark_modified();
+        state
+    }
+
+    // Pasting a single line into the new state prompt extends the name, while
+    // pasting several lines creates one state per line.
+    fn handle_paste(&mut self, text: &str) {
+        if let Some(browser) = &mut self.file_browser {
+            browser.paste(text);
+        } else if let Some(prompt) = &mut self.prompt {
+            if prompt.kind == PromptKind::Chain && text.contains('\n') {
+                self.prompt = None;
+                for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                    self.capture_chain(line);
+                }
+            } else {
+                prompt
+                    .input
+                    .push_str(text.lines().next().unwrap_or_default());
+            }
+        } else if let Some(new_state_name) = &mut self.new_state_name {
+            if !text.contains('\n') {
+                new_state_name.push_str(text.trim_end_matches('\r'));
+                return;
+            }
+
+            let names: Vec<String> = text
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string)
+                .collect();
+
+            self.new_state_name = None;
+            self.status = Some(t!("status.created", count = names.len()));
+            for name in names {
+                self.create_state(name);
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        self.status = None;
+
+        // Any key dismisses an error, without doing anything else.
+        if self.error.take().is_some() {
+            return;
+        }
+
+        let typing = self.file_browser.is_some()
+            || self.presenting
+            || self.confirm_quit
+            || self.prompt.is_some()
+            || self.split.is_some()
+            || self.replacement.is_some()
+            || self.new_state_name.is_some();
+        match self.macros.handle(key, !typing) {
+            macros::Outcome::Pass => (),
+            macros::Outcome::Handled => return,
+            macros::Outcome::Recording(register) => {
+                self.status = Some(t!("status.macro_recording", register = register));
+                return;
+            }
+            macros::Outcome::Recorded(register, count) => {
+                self.status = Some(t!(
+                    "status.macro_recorded",
+                    count = count,
+                    register = register
+                ));
+                return;
+            }
+            macros::Outcome::Play(keys) => {
+                let count = keys.len();
+                for key in keys {
+                    self.handle_key(key);
+                    if self.exit {
+                        return;
+                    }
+                }
+                if self.status.is_none() {
+                    self.status = Some(t!("status.macro_played", count = count));
+                }
+                return;
+            }
+            macros::Outcome::Error(message) => {
+                self.status = Some(message);
+                return;
+            }
+        }
+
+        if self.file_browser.is_some() {
+            self.handle_file_browser_key(key);
+            return;
+        }
+
+        if self.presenting {
+            self.handle_presentation_key(key);
+            return;
+        }
+
+        if self.confirm_quit {
+            match key.code {
+                KeyCode::Char('y') => {
+                    self.quit_after_save = true;
+                    self.save_or_save_as();
+                }
+                KeyCode::Char('n') => self.exit = true,
+                KeyCode::Esc => {}
+                _ => return,
+            }
+
+            self.confirm_quit = false;
+            return;
+        }
+
+        if let Some(path) = self.overwrite.take() {
+            match key.code {
+                KeyCode::Char('y') => self.write(path),
+                KeyCode::Char('d') => {
+                    match storage::backend(&path).and_then(|backend| backend.load()) {
+                        Ok(machine) => {
+                            self.snapshots.retain(|(n, _)| n != DISK_SNAPSHOT);
+                            self.snapshots
+                                .push((DISK_SNAPSHOT.to_string(), Snapshot::take(&machine)));
+                            self.diff_against = Some(DISK_SNAPSHOT.to_string());
+                        }
+                        Err(e) => self.report(t!("error.open", path = path.display(), error = e)),
+                    }
+                    self.overwrite = Some(path);
+                }
+                KeyCode::Char('S') => self.open_file_browser(file_browser::Mode::SaveAs),
+                KeyCode::Esc => self.quit_after_save = false,
+                _ => self.overwrite = Some(path),
+            }
+            return;
+        }
+
+        if self.split.is_some() {
+            self.handle_split_key(key);
+            return;
+        }
+
+        if let Some(replacement) = self.replacement.take() {
+            match key.code {
+                KeyCode::Enter => self.replace(&replacement),
+                KeyCode::Esc => {}
+                _ => self.replacement = Some(replacement),
+            }
+            return;
+        }
+
+        // Saving works even while typing into a prompt.
+        if self.keymap.action(key, |_| false) == Some(Action::Save) {
+            self.perform(Action::Save);
+            return;
+        }
+
+        // Prompts come before the read-only check, since typing into them
+        // changes nothing until they are submitted.
+        if let Some(prompt) = &mut self.prompt {
+            match key.code {
+                KeyCode::Char(ch) => prompt.input.push(ch),
+                KeyCode::Backspace => {
+                    prompt.input.pop();
+                }
+                KeyCode::Enter => {
+                    let prompt = self.prompt.take().unwrap();
+                    self.submit_prompt(prompt);
+                }
+                KeyCode::Esc => self.prompt = None,
+                _ => (),
+            }
+
+            return;
+        }
+
+        if let Some(ref mut new_state_name) = self.new_state_name {
+            match key.code {
+                KeyCode::Char(ch) => new_state_name.push(ch),
+                KeyCode::Backspace => {
+                    new_state_name.pop();
+                }
+                KeyCode::Enter => {
+                    let name = self.new_state_name.take().unwrap();
+                    self.create_state(name);
+                }
+                KeyCode::Esc => self.new_state_name = None,
+                _ => (),
+            }
+
+            return;
+        }
+
+        if let Some(action) = self.keymap.action(key, |when| self.is_active(when)) {
+            self.perform(action);
+        }
+    }
+
+    // Turns `from -> to` into `from -> name -> to`, with the new state where
+    // the middle of the transition was drawn. A note stays on the first
+    // half.
+    fn insert_state(
+        &mut self,
+        from: &Rc<RefCell<FSMState>>,
+        to: &Rc<RefCell<FSMState>>,
+        name: String,
+    ) {
+        let middle = {
+            let from = from.borrow();
+            let bend = from
+                .bend(Rc::as_ptr(to))
+                .unwrap_or(Vector2D { x: 0.0, y: 0.0 });
+            (from.position() + to.borrow().position()) * 0.5 + bend * 0.5
+        };
+
+        let inserted = self.create_state(name);
+        {
+            let mut state = inserted.borrow_mut();
+            (state.x, state.y) = middle.into();
+            state.next_states.push(Rc::downgrade(to));
+        }
+        self.index.rebuild(&self.states, &self.config.states);
+
+        let mut from = from.borrow_mut();
+        let note = from.note(Rc::as_ptr(to)).map(str::to_string);
+        from.set_note(to, None);
+        from.set_bend(to, None);
+        for next in &mut from.next_states {
+            if std::ptr::eq(next.as_ptr(), Rc::as_ptr(to)) {
+                *next = Rc::downgrade(&inserted);
+            }
+        }
+        from.set_note(&inserted, note);
+        self.mark_modified();
+    }
+
+    fn replace(&mut self, replacement: &Replacement) {
+        let (find, with) = (&replacement.find, &replacement.with);
+
+        for state in &self.states {
+            let mut state = state.borrow_mut();
+            state.name = find.replace(&state.name, with);
+            for (_, note) in &mut state.notes {
+                *note = find.replace(note, with);
+            }
+        }
+        // Circles grow or shrink with the names.
+        self.index.rebuild(&self.states, &self.config.states);
+
+        self.status = Some(t!("status.replaced", count = replacement.preview.len()));
+        self.mark_modified();
+    }
+
+    fn handle_split_key(&mut self, key: KeyEvent) {
+        let Some(split) = &mut self.split else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Up => split.cursor = split.cursor.saturating_sub(1),
+            KeyCode::Down => {
+                split.cursor = (split.cursor + 1).min(split.incoming.len().saturating_sub(1))
+            }
+            KeyCode::Char(' ') => {
+                if let Some((_, moves)) = split.incoming.get_mut(split.cursor) {
+                    *moves = !*moves;
+                }
+            }
+            KeyCode::Enter => {
+                let split = self.split.take().unwrap();
+                self.split_state(split);
+            }
+            KeyCode::Esc => self.split = None,
+            _ => (),
+        }
+    }
+
+    // Adds the copy beside the original, with the same outgoing transitions,
+    // and moves the chosen incoming transitions over to it.
+    fn split_state(&mut self, split: Split) {
+        let Some(original) = split.original.upgrade() else {
+            return;
+        };
+
+        let copy = self.create_state(split.name.clone());
+        {
+            let from = original.borrow();
+            let mut to = copy.borrow_mut();
+            let offset = from.circle_radius(&self.config.states) * 4.0;
+            (to.x, to.y) = (from.x + offset, from.y);
+            to.accepting = from.accepting;
+            to.pseudo = from.pseudo;
+            // A loop on the original becomes a loop on the copy.
+            let retarget = |next: &Weak<RefCell<FSMState>>| match std::ptr::eq(
+                next.as_ptr(),
+                Rc::as_ptr(&original),
+            ) {
+                true => Rc::downgrade(&copy),
+                false => next.clone(),
+            };
+            to.next_states = from.next_states.iter().map(retarget).collect();
+            to.bends = from
+                .bends
+                .iter()
+                .map(|(next, offset)| (retarget(next), *offset))
+                .collect();
+            to.notes = from
+                .notes
+                .iter()
+                .map(|(next, text)| (retarget(next), text.clone()))
+                .collect();
+        }
+        self.index.rebuild(&self.states, &self.config.states);
+
+        let moved: Vec<_> = split
+            .incoming
+            .iter()
+            .filter(|(_, moves)| *moves)
+            .filter_map(|(from, _)| from.upgrade())
+            .collect();
+        for from in &moved {
+            let mut from = from.borrow_mut();
+            let note = from.note(Rc::as_ptr(&original)).map(str::to_string);
+            from.set_note(&original, None);
+            from.set_bend(&original, None);
+            from.next_states
+                .retain(|n| !std::ptr::eq(n.as_ptr(), Rc::as_ptr(&original)));
+            from.next_states.push(Rc::downgrade(&copy));
+            from.set_note(&copy, note);
+        }
+
+        self.status = Some(t!(
+            "status.split",
+            name = split.name,
+            original = original.borrow().name,
+            count = moved.len()
+        ));
+        self.mark_modified();
+    }
+
+    fn is_active(&self, when: When) -> bool {
+        match when {
+            When::Always => true,
+            When::Diagnostics => self.show_diagnostics,
+            When::Properties => self.show_properties,
+            When::History => self.show_history,
+            When::ListMode => self.list_mode,
+        }
+    }
+
+    // Does what the key bound to `action` does.
+    fn perform(&mut self, action: Action) {
+        if self.read_only && action.edits() {
+            self.status = Some(t!("status.read_only"));
+            return;
+        }
+
+        match action {
+            Action::Quit => {
+                if self.modified {
+                    self.confirm_quit = true;
+                } else {
+                    self.exit = true;
+                }
+            }
+            Action::MarkSource => {
+                self.secondary_selected_state = std::mem::take(&mut self.selected_state);
+            }
+            Action::ToggleConnection => {
+                if let (Some(selected_state), Some(secondary_state)) = (
+                    self.selected_state.upgrade(),
+                    self.secondary_selected_state.upgrade(),
+                ) {
+                    let old_secondary_next_count = secondary_state.borrow().next_states.len();
+                    secondary_state.borrow_mut().next_states.retain(|s| {
+                        if let Some(s2) = s.upgrade() {
+                            !Rc::ptr_eq(&s2, &selected_state)
+                        } else {
+                            true
+                        }
+                    });
+
+                    if old_secondary_next_count == secondary_state.borrow().next_states.len() {
+                        secondary_state
+                            .borrow_mut()
+                            .next_states
+                            .push(Rc::downgrade(&selected_state));
+                    } else {
+                        let mut secondary_state = secondary_state.borrow_mut();
+                        secondary_state.set_bend(&selected_state, None);
+                        secondary_state.set_note(&selected_state, None);
+                    }
+
+                    self.mark_modified();
+                    self.selected_state = Weak::new();
+                    self.secondary_selected_state = Weak::new();
+                }
+            }
+            Action::DeleteState => {
+                let selected = self.selected_state.upgrade().and_then(|state| {
+                    let index = self.states.iter().position(|s| Rc::ptr_eq(s, &state))?;
+                    Some((state, index))
+                });
+                if let Some((state, index)) = selected {
+                    self.index.remove(&state);
+                    self.states.swap_remove(index);
+                    self.mark_modified();
+                }
+            }
+            Action::NewState => self.new_state_name = Some(String::new()),
+            Action::CycleMarker => {
+                let markers = [
+                    Marker::Dot,
+                    Marker::Block,
+                    Marker::Bar,
+                    Marker::Braille,
+                    Marker::HalfBlock,
+                ];
+
+                self.marker = *markers
+                    .iter()
+                    .skip_while(|m| **m != self.marker)
+                    .nth(1)
+                    .unwrap_or(&markers[0]);
+            }
+            Action::ReplayPath => {
+                if self.highlighted_path.len() < 2 {
+                    self.status = Some(t!("status.no_witness"));
+                } else {
+                    self.path_step = Some(0);
+                }
+            }
+            Action::ShortestPath => {
+                let (Some(from), Some(to)) = (
+                    self.secondary_selected_state.upgrade(),
+                    self.selected_state.upgrade(),
+                ) else {
+                    self.status = Some(t!("status.select_endpoints"));
+                    return;
+                };
+
+                let (from_name, to_name) = (from.borrow().name.clone(), to.borrow().name.clone());
+
+                if let Some(path) = analysis::shortest_path(&from, &to) {
+                    self.status = Some(t!(
+                        "status.reachable",
+                        to = to_name,
+                        from = from_name,
+                        count = path.len() - 1
+                    ));
+                    self.highlighted_path = path.iter().map(Rc::downgrade).collect();
+                    self.path_step = None;
+                } else {
+                    self.status = Some(t!("status.unreachable", to = to_name, from = from_name));
+                    self.highlighted_path.clear();
+                    self.path_step = None;
+                }
+            }
+            Action::ToggleAccepting => {
+                if let Some(selected) = self.selected_state.upgrade() {
+                    let mut selected = selected.borrow_mut();
+                    selected.accepting = !selected.accepting;
+                    self.mark_modified();
+                }
+            }
+            Action::ToggleDiagnostics => {
+                self.show_diagnostics = !self.show_diagnostics;
+                self.issue_cursor = None;
+            }
+            Action::NextIssue => self.jump_to_issue(true),
+            Action::PreviousIssue => self.jump_to_issue(false),
+            Action::ToggleProperties => self.show_properties = !self.show_properties,
+            Action::ToggleMetadata => self.show_metadata = !self.show_metadata,
+            Action::Undo => {
+                let label = self.history.current_label().to_string();
+                match self.history.undo().cloned() {
+                    Some(snapshot) => {
+                        self.restore_snapshot(snapshot);
+                        tracing::info!(change = label, "undone");
+                        self.status = Some(t!("status.undone", change = label));
+                    }
+                    None => self.status = Some(t!("status.nothing_to_undo")),
+                }
+            }
+            Action::Redo => match self.history.redo().cloned() {
+                Some(snapshot) => {
+                    self.restore_snapshot(snapshot);
+                    tracing::info!(change = self.history.current_label(), "redone");
+                    self.status = Some(t!("status.redone", change = self.history.current_label()));
+                }
+                None => self.status = Some(t!("status.nothing_to_redo")),
+            },
+            Action::ToggleHistory => {
+                self.show_history = !self.show_history;
+                self.history_cursor = self
+                    .history
+                    .entries()
+                    .iter()
+                    .position(|e| e.current)
+                    .unwrap_or(0);
+            }
+            Action::PreviousHistoryEntry => {
+                self.history_cursor = self.history_cursor.saturating_sub(1)
+            }
+            Action::NextHistoryEntry => {
+                self.history_cursor =
+                    (self.history_cursor + 1).min(self.history.entries().len() - 1)
+            }
+            Action::GoToHistoryEntry => {
+                let entries = self.history.entries();
+                let entry = &entries[self.history_cursor.min(entries.len() - 1)];
+                if let Some(snapshot) = self.history.jump(entry.id).cloned() {
+                    self.restore_snapshot(snapshot);
+                    tracing::info!(version = entry.label, "went back");
+                    self.status = Some(t!("status.went_back", version = entry.label));
+                }
+            }
+            Action::AddProperty => self.open_prompt(PromptKind::Property),
+            Action::ChainPrompt => self.open_prompt(PromptKind::Chain),
+            Action::CommandPrompt => self.open_prompt(PromptKind::Command),
+            Action::RemoveProperty => {
+                if self.property_cursor < self.properties.len() {
+                    self.properties.remove(self.property_cursor);
+                    self.property_cursor = self.property_cursor.saturating_sub(1);
+                    self.mark_modified();
+                }
+            }
+            Action::PreviousProperty => {
+                self.property_cursor = self.property_cursor.saturating_sub(1)
+            }
+            Action::NextProperty => {
+                self.property_cursor =
+                    (self.property_cursor + 1).min(self.properties.len().saturating_sub(1))
+            }
+            Action::Prune => {
+                if !self.has_accepting_states() {
+                    self.status = Some(t!("status.no_accepting"));
+                    return;
+                }
+
+                let pruned = analysis::cannot_accept(&self.states);
+                self.status = Some(t!("status.pruned", count = pruned.len()));
+                self.remove_states(&pruned);
+                self.mark_modified();
+            }
+            Action::CyclePseudoState => {
+                if let Some(selected) = self.selected_state.upgrade() {
+                    let mut selected = selected.borrow_mut();
+                    selected.pseudo = match selected.pseudo {
+                        None => Some(Pseudo::Choice),
+                        Some(Pseudo::Choice) => Some(Pseudo::Junction),
+                        Some(Pseudo::Junction) => None,
+                    };
+                    self.mark_modified();
+                }
+            }
+            Action::MakeInitial => {
+                if self.selected_state.upgrade().is_some() {
+                    self.initial_state = self.selected_state.clone();
+                    self.mark_modified();
+                }
+            }
+            Action::ToggleDepths => self.show_depths = !self.show_depths,
+            Action::ToggleClusters => self.clustered = !self.clustered,
+            Action::ToggleRulers => self.show_rulers = !self.show_rulers,
+            Action::ToggleHelp => self.show_help = !self.show_help,
+            Action::FilterPaths => {
+                if self.path_filter.take().is_some() {
+                    return;
+                }
+
+                // Without a source, paths start at the initial state.
+                let from = self
+                    .secondary_selected_state
+                    .upgrade()
+                    .or_else(|| self.initial_state.upgrade());
+                let (Some(from), Some(to)) = (from, self.selected_state.upgrade()) else {
+                    self.status = Some(t!("status.select_endpoints"));
+                    return;
+                };
+
+                let (from_name, to_name) = (from.borrow().name.clone(), to.borrow().name.clone());
+                let count = analysis::between(&self.states, &from, &to).len();
+                if count == 0 {
+                    self.status = Some(t!("status.unreachable", to = to_name, from = from_name));
+                    return;
+                }
+
+                self.status = Some(t!(
+                    "status.filtered",
+                    count = count,
+                    from = from_name,
+                    to = to_name
+                ));
+                self.path_filter = Some([Rc::downgrade(&from), Rc::downgrade(&to)]);
+            }
+            Action::Layer => self.snap_to_layers(),
+            Action::Open => self.open_file_browser(file_browser::Mode::Open),
+            Action::SaveAs => self.open_file_browser(file_browser::Mode::SaveAs),
+            Action::Generate => {
+                let machine = generator::random_machine(50, 0.05, &mut generator::Rng::from_time());
+                self.replace_machine(machine);
+                self.mark_modified();
+            }
+            Action::TogglePerf => self.show_perf = !self.show_perf,
+            Action::ToggleReadOnly => self.read_only = !self.read_only,
+            Action::Present => self.presenting = true,
+            Action::ToggleListMode => self.list_mode = !self.list_mode,
+            Action::ToggleMonochrome => self.monochrome = !self.monochrome,
+            Action::ResetView => {
+                self.reset_view();
+            }
+            Action::SelectNext => self.select_relative(1),
+            Action::Unselect => {
+                self.selected_state = Weak::new();
+                self.secondary_selected_state = Weak::new();
+                self.highlighted_path.clear();
+                self.path_step = None;
+            }
+            Action::SelectPrevious => self.select_relative(-1),
+            Action::MoveLeft => self.move_selected(-self.config.move_step, 0.0),
+            Action::MoveRight => self.move_selected(self.config.move_step, 0.0),
+            Action::MoveUp => self.move_selected(0.0, self.config.move_step),
+            Action::MoveDown => self.move_selected(0.0, -self.config.move_step),
+            Action::BendLeft => self.bend_selected(-self.config.move_step, 0.0),
+            Action::BendRight => self.bend_selected(self.config.move_step, 0.0),
+            Action::BendUp => self.bend_selected(0.0, self.config.move_step),
+            Action::BendDown => self.bend_selected(0.0, -self.config.move_step),
+            Action::Straighten => {
+                if let Some([from, to]) = self.selected_transition() {
+                    from.borrow_mut().set_bend(&to, None);
+                    self.mark_modified();
+                }
+            }
+            Action::Save => self.save_or_save_as(),
+        }
+    }
+
+    fn has_accepting_states(&self) -> bool {
+        self.states.iter().any(|s| s.borrow().accepting)
+    }
+
+    // Moves the transitions of `gone` over to `kept` and removes `gone`, for
+    // two states that turned out to be equivalent. Notes move along where
+    // `kept` has none, while bends are dropped since the shape changes.
+    fn merge_states(&mut self, kept: &Rc<RefCell<FSMState>>, gone: &Rc<RefCell<FSMState>>) {
+        let is = |weak: &Weak<RefCell<FSMState>>, state: &Rc<RefCell<FSMState>>| {
+            std::ptr::eq(weak.as_ptr(), Rc::as_ptr(state))
+        };
+        // Transitions to `gone` now lead to `kept`.
+        let redirect = |weak: &Weak<RefCell<FSMState>>| match is(weak, gone) {
+            true => Rc::downgrade(kept),
+            false => weak.clone(),
+        };
+
+        let (outgoing, notes) = {
+            let gone = gone.borrow();
+            (gone.next_states.clone(), gone.notes.clone())
+        };
+        for next in &outgoing {
+            let note = notes
+                .iter()
+                .find(|(to, _)| to.ptr_eq(next))
+                .map(|(_, note)| note.clone());
+            let next = redirect(next);
+
+            let mut kept = kept.borrow_mut();
+            if !kept.next_states.iter().any(|n| n.ptr_eq(&next)) {
+                kept.next_states.push(next.clone());
+            }
+            let (Some(note), Some(to)) = (note, next.upgrade()) else {
+                continue;
+            };
+            if kept.note(Rc::as_ptr(&to)).is_none() {
+                kept.set_note(&to, Some(note));
+            }
+        }
+
+        for state in self.states.iter().filter(|s| !Rc::ptr_eq(s, gone)) {
+            let mut state = state.borrow_mut();
+            if !state.next_states.iter().any(|n| is(n, gone)) {
+                continue;
+            }
+
+            let note = state.note(Rc::as_ptr(gone)).map(str::to_string);
+            state.set_note(gone, None);
+            state.set_bend(gone, None);
+
+            let mut next_states: Vec<Weak<RefCell<FSMState>>> = vec![];
+            for next in state.next_states.iter().map(redirect) {
+                if !next_states.iter().any(|n| n.ptr_eq(&next)) {
+                    next_states.push(next);
+                }
+            }
+            state.next_states = next_states;
+
+            if state.note(Rc::as_ptr(kept)).is_none() {
+                state.set_note(kept, note);
+            }
+        }
+
+        let accepting = gone.borrow().accepting;
+        kept.borrow_mut().accepting |= accepting;
+        if is(&self.initial_state, gone) {
+            self.initial_state = Rc::downgrade(kept);
+        }
+        self.remove_states(std::slice::from_ref(gone));
+    }
+
+    fn remove_states(&mut self, removed: &[Rc<RefCell<FSMState>>]) {
+        self.states
+            .retain(|s| !removed.iter().any(|r| Rc::ptr_eq(s, r)));
+        self.index.rebuild(&self.states, &self.config.states);
+    }
+
+    fn find_snapshot(&self, name: &str) -> Option<&Snapshot> {
+        self.snapshots
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, snapshot)| snapshot)
+    }
+
+    // Moves the cursor in the diagnostics panel and selects the offending
+    // state, if there is one.
+    fn jump_to_issue(&mut self, forward: bool) {
+        let issues = diagnostics::collect(&self.states, &self.initial_state, &self.properties);
+        if issues.is_empty() {
+            return;
+        }
+
+        let cursor = match (self.issue_cursor, forward) {
+            (None, true) => 0,
+            (None, false) => issues.len() - 1,
+            (Some(cursor), true) => (cursor + 1) % issues.len(),
+            (Some(cursor), false) => (cursor + issues.len() - 1) % issues.len(),
+        };
+        self.issue_cursor = Some(cursor);
+
+        let state = &issues[cursor].state;
+        if state.strong_count() > 0 {
+            self.selected_state = state.clone();
+        }
     }
 }
@@ -0,0 +1,31 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{EdgeStyles, StateStyle};
+use crate::keymap::Entry;
+
+// Key bindings and canvas styles kept in a file of their own, so a class or
+// team can hand one around instead of everyone editing their config. The
+// bindings replace the default ones entirely.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub keys: Vec<Entry>,
+    #[serde(default)]
+    pub edges: EdgeStyles,
+    #[serde(default)]
+    pub states: StateStyle,
+}
+
+pub fn load(path: impl AsRef<Path>) -> std::io::Result<Profile> {
+    let profile: Profile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    profile.states.validate()?;
+    Ok(profile)
+}
+
+pub fn save(path: impl AsRef<Path>, profile: &Profile) -> std::io::Result<()> {
+    let mut text = serde_json::to_string_pretty(profile)?;
+    text.push('\n');
+    std::fs::write(path, text)
+}
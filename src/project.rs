@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use crate::machine_file::{self, Machine, Snapshot};
+
+// A project keeps several machines, with their snapshots, in one SQLite
+// file. A machine in it is located as `PROJECT.fsmproj#NAME`.
+pub const EXTENSION: &str = "fsmproj";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS machines (
+    name TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    author TEXT NOT NULL,
+    states INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    machine BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshots (
+    machine TEXT NOT NULL REFERENCES machines (name) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    snapshot BLOB NOT NULL,
+    PRIMARY KEY (machine, name)
+);
+";
+
+pub fn is_project(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == EXTENSION)
+}
+
+// Splits a location into the project file and the machine in it, if it
+// points into a project.
+pub fn split(location: &Path) -> Option<(PathBuf, Option<String>)> {
+    let text = location.to_string_lossy();
+    match text.rsplit_once('#') {
+        Some((path, name)) if is_project(Path::new(path)) => {
+            Some((PathBuf::from(path), Some(name.to_string())))
+        }
+        _ => is_project(location).then(|| (location.to_path_buf(), None)),
+    }
+}
+
+pub fn location(path: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}#{name}", path.display()))
+}
+
+// What the project browser lists about a machine, without decoding it.
+pub struct Entry {
+    pub name: String,
+    pub title: String,
+    pub states: usize,
+    pub snapshots: usize,
+}
+
+pub struct Project(Connection);
+
+fn error(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+impl Project {
+    // Opens an existing project, or creates it when `create` is set.
+    pub fn open(path: &Path, create: bool) -> std::io::Result<Self> {
+        if !create && !path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                t!("project.missing", path = path.display()),
+            ));
+        }
+
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+        )
+        .map_err(error)?;
+        connection
+            .execute_batch(&format!("PRAGMA foreign_keys = ON;{SCHEMA}"))
+            .map_err(error)?;
+        Ok(Self(connection))
+    }
+
+    pub fn machines(&self) -> std::io::Result<Vec<Entry>> {
+        let mut statement = self
+            .0
+            .prepare(
+                "SELECT name, title, states,
+                    (SELECT COUNT(*) FROM snapshots WHERE machine = machines.name)
+                 FROM machines ORDER BY name",
+            )
+            .map_err(error)?;
+        let entries = statement
+            .query_map([], |row| {
+                Ok(Entry {
+                    name: row.get(0)?,
+                    title: row.get(1)?,
+                    states: row.get(2)?,
+                    snapshots: row.get(3)?,
+                })
+            })
+            .map_err(error)?;
+        entries.collect::<Result<_, _>>().map_err(error)
+    }
+
+    // The machine as stored, to notice when someone else changed it.
+    pub fn raw(&self, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+        self.0
+            .query_row(
+                "SELECT machine FROM machines WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(error)
+    }
+
+    pub fn load(&self, name: &str) -> std::io::Result<Machine> {
+        match self.raw(name)? {
+            Some(json) => machine_file::read(json.as_slice()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                t!("project.no_machine", name = format!("{name:?}")),
+            )),
+        }
+    }
+
+    pub fn save(&self, name: &str, machine: &Machine) -> std::io::Result<()> {
+        let modified = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        self.0
+            .execute(
+                "INSERT INTO machines (name, title, author, states, modified, machine)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (name) DO UPDATE SET title = ?2, author = ?3, states = ?4,
+                    modified = ?5, machine = ?6",
+                params![
+                    name,
+                    machine.metadata.title,
+                    machine.metadata.author,
+                    machine.states.len(),
+                    modified,
+                    machine_file::to_json(machine)?,
+                ],
+            )
+            .map(drop)
+            .map_err(error)
+    }
+
+    pub fn snapshots(&self, name: &str) -> std::io::Result<Vec<(String, Snapshot)>> {
+        let mut statement = self
+            .0
+            .prepare("SELECT name, snapshot FROM snapshots WHERE machine = ?1 ORDER BY rowid")
+            .map_err(error)?;
+        let rows = statement
+            .query_map([name], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(error)?;
+
+        let mut snapshots = vec![];
+        for row in rows {
+            let (snapshot, json) = row.map_err(error)?;
+            let machine = machine_file::read(json.as_slice())?;
+            snapshots.push((snapshot, Snapshot::take(&machine)));
+        }
+        Ok(snapshots)
+    }
+
+    // Snapshots belong to a machine, so this needs it saved first.
+    pub fn save_snapshot(
+        &self,
+        name: &str,
+        snapshot: &str,
+        machine: &Machine,
+    ) -> std::io::Result<()> {
+        let saved = self
+            .0
+            .query_row("SELECT 1 FROM machines WHERE name = ?1", [name], |_| Ok(()))
+            .optional()
+            .map_err(error)?;
+        if saved.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                t!("project.unsaved", name = format!("{name:?}")),
+            ));
+        }
+
+        self.0
+            .execute(
+                "INSERT OR REPLACE INTO snapshots (machine, name, snapshot) VALUES (?1, ?2, ?3)",
+                params![name, snapshot, machine_file::to_json(machine)?],
+            )
+            .map(drop)
+            .map_err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("fsmtui-{}-{name}.fsmproj", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn splits_locations() {
+        assert_eq!(
+            split(Path::new("a/plans.fsmproj#door")),
+            Some((PathBuf::from("a/plans.fsmproj"), Some("door".to_string())))
+        );
+        assert_eq!(
+            split(Path::new("plans.fsmproj")),
+            Some((PathBuf::from("plans.fsmproj"), None))
+        );
+        assert_eq!(split(Path::new("notes#1.json")), None);
+        assert_eq!(
+            location(Path::new("plans.fsmproj"), "door"),
+            PathBuf::from("plans.fsmproj#door")
+        );
+    }
+
+    #[test]
+    fn keeps_machines_and_snapshots() {
+        let path = temporary("round-trip");
+        let project = Project::open(&path, true).unwrap();
+        let door = crate::import::regex("ab*").unwrap();
+        let light = crate::import::regex("on|off").unwrap();
+        project.save("door", &door).unwrap();
+        project.save("light", &light).unwrap();
+        project.save_snapshot("door", "first", &door).unwrap();
+
+        let project = Project::open(&path, false).unwrap();
+        let names: Vec<String> = project
+            .machines()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["door", "light"]);
+        assert!(Snapshot::take(&project.load("door").unwrap()) == Snapshot::take(&door));
+
+        let snapshots = project.snapshots("door").unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].0, "first");
+        assert!(project.load("lamp").is_err());
+
+        let error = project.save_snapshot("lamp", "first", &door).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert!(project.snapshots("lamp").unwrap().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn does_not_create_when_opening() {
+        let path = temporary("missing");
+        assert!(Project::open(&path, false).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::analysis;
+use crate::FSMState;
+
+type StateRef = Rc<RefCell<FSMState>>;
+
+// A property the user expects the machine to keep. It is checked whenever the
+// machine is drawn, so the result always reflects the latest edit. States are
+// referred to by name so properties can be written before the states exist
+// and survive being saved.
+#[derive(Clone, PartialEq)]
+pub enum Property {
+    Reachable { from: String, to: String },
+    Unreachable { from: String, to: String },
+    AlwaysReaches { from: String, to: String },
+}
+
+pub struct Failure {
+    pub reason: String,
+    // The state that shows the property is broken, if there is one.
+    pub state: Option<StateRef>,
+}
+
+impl Failure {
+    fn new(reason: String, state: Option<StateRef>) -> Self {
+        Self { reason, state }
+    }
+}
+
+// How each kind of property is written. The wording is fixed, as it is
+// also how properties are saved.
+const FORMS: [&str; 3] = [
+    "B is reachable from A",
+    "B is unreachable from A",
+    "every path from A reaches B",
+];
+
+pub fn syntax() -> String {
+    let [reachable, unreachable, always] = FORMS;
+    t!(
+        "property.syntax",
+        reachable = reachable,
+        unreachable = unreachable,
+        always = always
+    )
+}
+
+impl Property {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let pair = |(a, b): (&str, &str)| (a.trim().to_string(), b.trim().to_string());
+
+        let property = if let Some(rest) = text.strip_prefix("every path from ") {
+            let (from, to) = pair(rest.split_once(" reaches ")?);
+            Self::AlwaysReaches { from, to }
+        } else if let Some(split) = text.split_once(" is unreachable from ") {
+            let (to, from) = pair(split);
+            Self::Unreachable { from, to }
+        } else {
+            let (to, from) = pair(text.split_once(" is reachable from ")?);
+            Self::Reachable { from, to }
+        };
+
+        let (Self::Reachable { from, to }
+        | Self::Unreachable { from, to }
+        | Self::AlwaysReaches { from, to }) = &property;
+
+        (!from.is_empty() && !to.is_empty()).then_some(property)
+    }
+
+    pub fn check(&self, states: &[StateRef]) -> Result<(), Failure> {
+        let find = |name: &str| {
+            states
+                .iter()
+                .find(|s| s.borrow().name == name)
+                .cloned()
+                .ok_or_else(|| Failure::new(t!("property.no_state", name = name), None))
+        };
+
+        match self {
+            Self::Reachable { from, to } => {
+                let (from, to) = (find(from)?, find(to)?);
+                match analysis::shortest_path(&from, &to) {
+                    Some(_) => Ok(()),
+                    None => Err(Failure::new(t!("property.no_path"), Some(to))),
+                }
+            }
+            Self::Unreachable { from, to } => {
+                let (from, to) = (find(from)?, find(to)?);
+                match analysis::shortest_path(&from, &to) {
+                    Some(path) => Err(Failure::new(
+                        t!("property.reachable_in", count = path.len() - 1),
+                        Some(to),
+                    )),
+                    None => Ok(()),
+                }
+            }
+            Self::AlwaysReaches { from, to } => {
+                let (from, to) = (find(from)?, find(to)?);
+                always_reaches(states, &from, &to)
+            }
+        }
+    }
+}
+
+// Every path from `from` reaches `to` exactly when `from` is in the set of
+// states that can't avoid `to`: `to` itself, plus every state that has
+// transitions and only leads into that set.
+fn always_reaches(states: &[StateRef], from: &StateRef, to: &StateRef) -> Result<(), Failure> {
+    let mut inevitable: HashSet<*const RefCell<FSMState>> = HashSet::from([Rc::as_ptr(to)]);
+
+    loop {
+        let grown: Vec<_> = states
+            .iter()
+            .filter(|s| !inevitable.contains(&Rc::as_ptr(s)))
+            .filter(|s| {
+                let next: Vec<_> = s
+                    .borrow()
+                    .next_states
+                    .iter()
+                    .filter_map(|n| n.upgrade())
+                    .collect();
+                !next.is_empty() && next.iter().all(|n| inevitable.contains(&Rc::as_ptr(n)))
+            })
+            .map(Rc::as_ptr)
+            .collect();
+
+        if grown.is_empty() {
+            break;
+        }
+        inevitable.extend(grown);
+    }
+
+    if inevitable.contains(&Rc::as_ptr(from)) {
+        return Ok(());
+    }
+
+    // Point at a dead end that can be reached while avoiding `to`, falling
+    // back to `from` when the escape is a cycle.
+    let mut seen = HashSet::from([Rc::as_ptr(from)]);
+    let mut stack = vec![from.clone()];
+    while let Some(state) = stack.pop() {
+        let next: Vec<_> = state
+            .borrow()
+            .next_states
+            .iter()
+            .filter_map(|n| n.upgrade())
+            .collect();
+
+        if next.is_empty() {
+            let name = state.borrow().name.clone();
+            return Err(Failure::new(
+                t!("property.path_ends", name = name),
+                Some(state),
+            ));
+        }
+
+        for next in next {
+            if !Rc::ptr_eq(&next, to) && seen.insert(Rc::as_ptr(&next)) {
+                stack.push(next);
+            }
+        }
+    }
+
+    Err(Failure::new(t!("property.cycle"), Some(from.clone())))
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Reachable { from, to } => write!(f, "{to} is reachable from {from}"),
+            Self::Unreachable { from, to } => write!(f, "{to} is unreachable from {from}"),
+            Self::AlwaysReaches { from, to } => write!(f, "every path from {from} reaches {to}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A leads to C through B and to the dead end D. E and F loop, and E can
+    // also leave for C.
+    fn states() -> Vec<StateRef> {
+        crate::import::mermaid(
+            "stateDiagram-v2\n[*] --> A\nA --> B\nB --> C\nA --> D\nE --> F\nF --> E\nE --> C\n",
+        )
+        .unwrap()
+        .states
+    }
+
+    fn failure(property: &str) -> Option<(String, Option<String>)> {
+        let property = Property::parse(property).unwrap();
+        property.check(&states()).err().map(|failure| {
+            let state = failure.state.map(|s| s.borrow().name.clone());
+            (failure.reason, state)
+        })
+    }
+
+    #[test]
+    fn parses_properties() {
+        let (from, to) = ("A".to_string(), "C".to_string());
+        assert!(
+            Property::parse(" C is reachable from A ")
+                == Some(Property::Reachable {
+                    from: from.clone(),
+                    to: to.clone()
+                })
+        );
+        assert!(
+            Property::parse("C is unreachable from A")
+                == Some(Property::Unreachable {
+                    from: from.clone(),
+                    to: to.clone()
+                })
+        );
+        assert!(
+            Property::parse("every path from Door Open reaches C")
+                == Some(Property::AlwaysReaches {
+                    from: "Door Open".to_string(),
+                    to
+                })
+        );
+        assert!(Property::parse("C is reachable from ").is_none());
+        assert!(Property::parse("every path from A").is_none());
+        assert!(Property::parse("A leads to C").is_none());
+    }
+
+    #[test]
+    fn writes_what_it_parses() {
+        for text in [
+            "C is reachable from A",
+            "C is unreachable from A",
+            "every path from A reaches C",
+        ] {
+            let property = Property::parse(text).unwrap();
+            assert_eq!(property.to_string(), text);
+            assert!(Property::parse(&property.to_string()) == Some(property));
+        }
+    }
+
+    #[test]
+    fn checks_reachability() {
+        assert_eq!(failure("C is reachable from A"), None);
+        assert_eq!(
+            failure("A is reachable from C"),
+            Some(("no path exists".to_string(), Some("A".to_string())))
+        );
+        assert_eq!(failure("A is unreachable from C"), None);
+        assert_eq!(
+            failure("C is unreachable from A"),
+            Some((
+                "reachable in 2 transitions".to_string(),
+                Some("C".to_string())
+            ))
+        );
+        assert_eq!(
+            failure("Z is reachable from A"),
+            Some(("there is no state Z".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn checks_that_every_path_reaches_a_state() {
+        assert_eq!(failure("every path from B reaches C"), None);
+        assert_eq!(failure("every path from C reaches C"), None);
+        assert_eq!(
+            failure("every path from A reaches C"),
+            Some(("a path ends in D".to_string(), Some("D".to_string())))
+        );
+        assert_eq!(
+            failure("every path from E reaches C"),
+            Some(("a cycle avoids it".to_string(), Some("E".to_string())))
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use ratatui::crossterm::event::{
+    Event, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+
+use crate::keymap;
+
+// Sessions are recorded as one event per line, so recordings stay readable
+// and can be written by hand for bug reports. Keys are named the way key
+// bindings name them, e.g. `n`, `Enter` or `Ctrl+s`, pastes are `paste`
+// followed by the text as a JSON string, and mouse events are `mouse` with
+// what happened at which terminal cell, e.g. `mouse drag left 40 12`. Those
+// only replay the same in a terminal of the same size.
+fn encode(event: &Event) -> Option<String> {
+    match event {
+        Event::Key(key) => keymap::key_name(key.code, key.modifiers),
+        Event::Paste(text) => Some(format!("paste {}", serde_json::to_string(text).ok()?)),
+        Event::Mouse(mouse) => {
+            let button = |button| BUTTONS.iter().find(|(b, _)| *b == button).map(|(_, n)| n);
+            let kind = match mouse.kind {
+                MouseEventKind::Down(b) => format!("down {}", button(b)?),
+                MouseEventKind::Up(b) => format!("up {}", button(b)?),
+                MouseEventKind::Drag(b) => format!("drag {}", button(b)?),
+                MouseEventKind::ScrollUp => "scroll-up".to_string(),
+                MouseEventKind::ScrollDown => "scroll-down".to_string(),
+                // Moving the pointer only changes the coordinates shown.
+                _ => return None,
+            };
+            Some(format!("mouse {kind} {} {}", mouse.column, mouse.row))
+        }
+        _ => None,
+    }
+}
+
+const BUTTONS: [(MouseButton, &str); 3] = [
+    (MouseButton::Left, "left"),
+    (MouseButton::Right, "right"),
+    (MouseButton::Middle, "middle"),
+];
+
+fn decode(line: &str) -> Option<Event> {
+    if let Some(text) = line.strip_prefix("paste ") {
+        return serde_json::from_str(text).ok().map(Event::Paste);
+    }
+
+    if let Some(rest) = line.strip_prefix("mouse ") {
+        let words: Vec<_> = rest.split_whitespace().collect();
+        let (kind, column, row) = match words[..] {
+            ["scroll-up", column, row] => (MouseEventKind::ScrollUp, column, row),
+            ["scroll-down", column, row] => (MouseEventKind::ScrollDown, column, row),
+            [kind, button, column, row] => {
+                let button = BUTTONS.iter().find(|(_, n)| *n == button)?.0;
+                let kind = match kind {
+                    "down" => MouseEventKind::Down(button),
+                    "up" => MouseEventKind::Up(button),
+                    "drag" => MouseEventKind::Drag(button),
+                    _ => return None,
+                };
+                (kind, column, row)
+            }
+            _ => return None,
+        };
+        return Some(Event::Mouse(MouseEvent {
+            kind,
+            column: column.parse().ok()?,
+            row: row.parse().ok()?,
+            modifiers: KeyModifiers::NONE,
+        }));
+    }
+
+    let (code, modifiers) = keymap::parse_key(line)?;
+    Some(Event::Key(KeyEvent::new(code, modifiers)))
+}
+
+pub struct Recorder {
+    file: std::fs::File,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            file: std::fs::File::create(path)?,
+        })
+    }
+
+    // Written unbuffered so the recording survives a crash, which is when it
+    // is needed most.
+    pub fn record(&mut self, event: &Event) -> std::io::Result<()> {
+        match encode(event) {
+            Some(line) => writeln!(self.file, "{line}"),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Replay {
+    events: VecDeque<Event>,
+    pub paused: bool,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let events = std::fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                decode(line.trim()).ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        t!(
+                            "recording.unknown",
+                            line = number + 1,
+                            text = format!("{line:?}")
+                        ),
+                    )
+                })
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self {
+            events,
+            paused: false,
+        })
+    }
+
+    pub fn next(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::crossterm::event::KeyCode;
+
+    fn round_trip(event: Event) {
+        let line = encode(&event).unwrap();
+        assert_eq!(decode(&line), Some(event), "{line}");
+    }
+
+    #[test]
+    fn round_trips_keys() {
+        let keys = [
+            (KeyCode::Char('n'), KeyModifiers::NONE),
+            (KeyCode::Char(' '), KeyModifiers::NONE),
+            (KeyCode::Char('s'), KeyModifiers::CONTROL),
+            (KeyCode::Left, KeyModifiers::ALT),
+            (KeyCode::Enter, KeyModifiers::NONE),
+            (KeyCode::F(5), KeyModifiers::CONTROL | KeyModifiers::ALT),
+        ];
+        for (code, modifiers) in keys {
+            round_trip(Event::Key(KeyEvent::new(code, modifiers)));
+        }
+        assert_eq!(
+            encode(&Event::Key(KeyEvent::new(
+                KeyCode::Char('s'),
+                KeyModifiers::CONTROL
+            ))),
+            Some("Ctrl+s".to_string())
+        );
+    }
+
+    #[test]
+    fn round_trips_pastes() {
+        round_trip(Event::Paste("Door open".to_string()));
+        round_trip(Event::Paste(" two\nlines with \"quotes\" ".to_string()));
+    }
+
+    #[test]
+    fn round_trips_mouse_events() {
+        let kinds = [
+            MouseEventKind::Down(MouseButton::Left),
+            MouseEventKind::Drag(MouseButton::Left),
+            MouseEventKind::Up(MouseButton::Left),
+            MouseEventKind::Down(MouseButton::Right),
+            MouseEventKind::ScrollUp,
+            MouseEventKind::ScrollDown,
+        ];
+        for kind in kinds {
+            round_trip(Event::Mouse(MouseEvent {
+                kind,
+                column: 40,
+                row: 12,
+                modifiers: KeyModifiers::NONE,
+            }));
+        }
+        let moved = MouseEvent {
+            kind: MouseEventKind::Moved,
+            column: 1,
+            row: 2,
+            modifiers: KeyModifiers::NONE,
+        };
+        assert_eq!(encode(&Event::Mouse(moved)), None);
+    }
+
+    #[test]
+    fn rejects_unknown_lines() {
+        assert_eq!(decode("Spacebar"), None);
+        assert_eq!(decode("mouse wiggle left 1 2"), None);
+        assert_eq!(decode("mouse down left 1"), None);
+        assert_eq!(decode("paste unquoted"), None);
+    }
+}
@@ -0,0 +1,283 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::{Rc, Weak};
+
+use crate::config::StateStyle;
+use crate::vector2d::Vector2D;
+use crate::FSMState;
+
+const CELL_SIZE: f64 = 50.0;
+
+type Cell = (i64, i64);
+type Entry = (Weak<RefCell<FSMState>>, f64);
+type Transition = [Weak<RefCell<FSMState>>; 2];
+type Middles = HashMap<Cell, Vec<(Vector2D, Transition)>>;
+
+// Uniform grid over state centers. Every state lives in exactly one cell, so
+// queries only have to look at the cells around the point of interest instead
+// of scanning every state.
+pub struct SpatialIndex {
+    // States with the radius they were inserted with.
+    cells: HashMap<Cell, Vec<Entry>>,
+    // How many states have each radius, keyed by its bits, which sort like
+    // the radii themselves as they are positive. The largest one bounds how
+    // far around a point queries have to look.
+    radii: BTreeMap<u64, usize>,
+    // The middles of transitions, where the mouse grabs them, in the same
+    // grid. Built on the first lookup after anything moved or changed shape.
+    middles: RefCell<Option<Middles>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self {
+            cells: HashMap::new(),
+            radii: BTreeMap::new(),
+            middles: RefCell::new(None),
+        }
+    }
+
+    fn cell(position: Vector2D) -> Cell {
+        (
+            (position.x / CELL_SIZE).floor() as i64,
+            (position.y / CELL_SIZE).floor() as i64,
+        )
+    }
+
+    // The cells within `reach` of `center`.
+    fn around(center: Vector2D, reach: f64) -> impl Iterator<Item = Cell> {
+        let (min_x, min_y) = Self::cell(center - Vector2D { x: reach, y: reach });
+        let (max_x, max_y) = Self::cell(center + Vector2D { x: reach, y: reach });
+        (min_x..=max_x).flat_map(move |cx| (min_y..=max_y).map(move |cy| (cx, cy)))
+    }
+
+    fn max_radius(&self) -> f64 {
+        self.radii
+            .last_key_value()
+            .map_or(0.0, |(&bits, _)| f64::from_bits(bits))
+    }
+
+    pub fn rebuild(&mut self, states: &[Rc<RefCell<FSMState>>], style: &StateStyle) {
+        self.cells.clear();
+        self.radii.clear();
+
+        for state in states {
+            self.insert(state, style);
+        }
+    }
+
+    pub fn insert(&mut self, state: &Rc<RefCell<FSMState>>, style: &StateStyle) {
+        let (cell, radius) = {
+            let state = state.borrow();
+            (Self::cell(state.position()), state.circle_radius(style))
+        };
+
+        *self.radii.entry(radius.to_bits()).or_default() += 1;
+        self.cells
+            .entry(cell)
+            .or_default()
+            .push((Rc::downgrade(state), radius));
+        self.transitions_changed();
+    }
+
+    // Must be called before the state's position changes, as the cell is
+    // looked up from the current position.
+    pub fn remove(&mut self, state: &Rc<RefCell<FSMState>>) {
+        let cell = Self::cell(state.borrow().position());
+
+        if let Some(entries) = self.cells.get_mut(&cell) {
+            let mut removed = vec![];
+            entries.retain(|(s, radius)| {
+                let keep = s.strong_count() > 0 && !std::ptr::eq(s.as_ptr(), Rc::as_ptr(state));
+                if !keep {
+                    removed.push(*radius);
+                }
+                keep
+            });
+
+            for radius in removed {
+                if let Some(count) = self.radii.get_mut(&radius.to_bits()) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.radii.remove(&radius.to_bits());
+                    }
+                }
+            }
+
+            if entries.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+        self.transitions_changed();
+    }
+
+    // To be called when transitions are added, removed or bent, so the
+    // middles are found again.
+    pub fn transitions_changed(&self) {
+        self.middles.borrow_mut().take();
+    }
+
+    fn states(&self) -> impl Iterator<Item = Rc<RefCell<FSMState>>> + '_ {
+        self.cells
+            .values()
+            .flatten()
+            .filter_map(|(state, _)| state.upgrade())
+    }
+
+    // Returns every state whose circle intersects the circle at `center`.
+    pub fn query(
+        &self,
+        center: Vector2D,
+        radius: f64,
+        style: &StateStyle,
+    ) -> Vec<Rc<RefCell<FSMState>>> {
+        let mut found = vec![];
+        for cell in Self::around(center, radius + self.max_radius()) {
+            let Some(entries) = self.cells.get(&cell) else {
+                continue;
+            };
+
+            for state in entries.iter().filter_map(|(s, _)| s.upgrade()) {
+                let hit = {
+                    let s = state.borrow();
+                    (s.position() - center).magnitude() < s.circle_radius(style) + radius
+                };
+
+                if hit {
+                    found.push(state);
+                }
+            }
+        }
+
+        found
+    }
+
+    // The state whose center is closest to `point`, if one is within
+    // `reach` of it.
+    pub fn nearest(&self, point: Vector2D, reach: f64) -> Option<Rc<RefCell<FSMState>>> {
+        Self::around(point, reach)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter_map(|(state, _)| state.upgrade())
+            .map(|state| {
+                let distance = (state.borrow().position() - point).magnitude();
+                (state, distance)
+            })
+            .filter(|(_, distance)| *distance <= reach)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(state, _)| state)
+    }
+
+    // The state drawn under `point`.
+    pub fn state_at(&self, point: Vector2D, style: &StateStyle) -> Option<Rc<RefCell<FSMState>>> {
+        self.nearest(point, self.max_radius()).filter(|s| {
+            (s.borrow().position() - point).magnitude() < s.borrow().circle_radius(style)
+        })
+    }
+
+    // The transition whose curve passes through its middle closest to
+    // `point`, if it is within `reach`. Self loops have no middle to grab.
+    pub fn transition_at(&self, point: Vector2D, reach: f64) -> Option<Transition> {
+        let mut middles = self.middles.borrow_mut();
+        let middles = middles.get_or_insert_with(|| {
+            let mut middles = Middles::new();
+            for from in self.states() {
+                let state = from.borrow();
+                for to in state.next_states.iter().filter_map(Weak::upgrade) {
+                    if Rc::ptr_eq(&from, &to) {
+                        continue;
+                    }
+
+                    // A quadratic curve passes halfway to its control point.
+                    let bend = state
+                        .bend(Rc::as_ptr(&to))
+                        .unwrap_or(Vector2D { x: 0.0, y: 0.0 });
+                    let middle = (state.position() + to.borrow().position()) * 0.5 + bend * 0.5;
+                    middles
+                        .entry(Self::cell(middle))
+                        .or_default()
+                        .push((middle, [Rc::downgrade(&from), Rc::downgrade(&to)]));
+                }
+            }
+            middles
+        });
+
+        Self::around(point, reach)
+            .filter_map(|cell| middles.get(&cell))
+            .flatten()
+            .map(|(middle, transition)| ((*middle - point).magnitude(), transition))
+            .filter(|(distance, _)| *distance < reach)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, transition)| transition.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(name: &str, x: f64, y: f64) -> Rc<RefCell<FSMState>> {
+        Rc::new(RefCell::new(FSMState::new(name.to_string(), x, y)))
+    }
+
+    #[test]
+    fn finds_the_nearest_state() {
+        let style = StateStyle::default();
+        let states = [state("a", 0.0, 0.0), state("b", 120.0, 0.0)];
+        let mut index = SpatialIndex::new();
+        index.rebuild(&states, &style);
+
+        let near = |x, reach| index.nearest(Vector2D { x, y: 0.0 }, reach);
+        assert!(near(70.0, 100.0).is_some_and(|s| Rc::ptr_eq(&s, &states[1])));
+        assert!(near(40.0, 100.0).is_some_and(|s| Rc::ptr_eq(&s, &states[0])));
+        assert!(near(60.0, 20.0).is_none());
+
+        assert!(index
+            .state_at(Vector2D { x: 3.0, y: 3.0 }, &style)
+            .is_some());
+        assert!(index
+            .state_at(Vector2D { x: 60.0, y: 0.0 }, &style)
+            .is_none());
+    }
+
+    #[test]
+    fn forgets_the_radius_of_removed_states() {
+        let style = StateStyle::default();
+        let (small, large) = (state("a", 0.0, 0.0), state("a long name", 500.0, 0.0));
+        let mut index = SpatialIndex::new();
+        index.rebuild(&[small.clone(), large.clone()], &style);
+        assert_eq!(index.max_radius(), large.borrow().circle_radius(&style));
+
+        index.remove(&large);
+        assert_eq!(index.max_radius(), small.borrow().circle_radius(&style));
+    }
+
+    #[test]
+    fn finds_transitions_by_their_middle() {
+        let style = StateStyle::default();
+        let states = [state("a", 0.0, 0.0), state("b", 200.0, 0.0)];
+        states[0]
+            .borrow_mut()
+            .next_states
+            .push(Rc::downgrade(&states[1]));
+        let mut index = SpatialIndex::new();
+        index.rebuild(&states, &style);
+
+        let [from, to] = index
+            .transition_at(Vector2D { x: 98.0, y: 4.0 }, 10.0)
+            .unwrap();
+        assert!(std::ptr::eq(from.as_ptr(), Rc::as_ptr(&states[0])));
+        assert!(std::ptr::eq(to.as_ptr(), Rc::as_ptr(&states[1])));
+
+        states[0]
+            .borrow_mut()
+            .set_bend(&states[1], Some(Vector2D { x: 0.0, y: 100.0 }));
+        index.transitions_changed();
+        assert!(index
+            .transition_at(Vector2D { x: 98.0, y: 4.0 }, 10.0)
+            .is_none());
+        assert!(index
+            .transition_at(Vector2D { x: 100.0, y: 50.0 }, 10.0)
+            .is_some());
+    }
+}
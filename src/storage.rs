@@ -0,0 +1,304 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::import;
+use crate::machine_file::{self, Machine, Snapshot};
+use crate::project::{self, Project};
+
+// Somewhere machines are loaded from and saved to. Locations are written
+// like paths everywhere, and anything that looks like a URI picks another
+// backend than plain files, e.g. `http://localhost:8000/machines/door.json`.
+pub trait Backend {
+    fn load(&self) -> std::io::Result<Machine>;
+    fn save(&self, machine: &Machine) -> std::io::Result<()>;
+
+    // Named snapshots kept along with the machine, by backends that can.
+    fn snapshots(&self) -> std::io::Result<Vec<(String, Snapshot)>> {
+        Ok(vec![])
+    }
+
+    fn save_snapshot(&self, _name: &str, _snapshot: &Snapshot) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn backend(location: &Path) -> std::io::Result<Box<dyn Backend>> {
+    if let Some((path, name)) = project::split(location) {
+        let Some(name) = name.filter(|n| !n.is_empty()) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                t!("storage.project_name"),
+            ));
+        };
+        return Ok(Box::new(ProjectMachine { path, name }));
+    }
+
+    let text = location.to_string_lossy();
+    let Some((scheme, rest)) = text.split_once("://") else {
+        return Ok(Box::new(File(location.to_path_buf())));
+    };
+
+    match scheme {
+        "file" => Ok(Box::new(File(PathBuf::from(rest)))),
+        "http" => Ok(Box::new(Http::parse(rest)?)),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            t!("storage.scheme", scheme = scheme),
+        )),
+    }
+}
+
+// Whether a location is a file on this machine, where files like rescued
+// changes can be written next to it. Machines in projects share their file.
+pub fn is_file(location: &Path) -> bool {
+    let text = location.to_string_lossy();
+    (text.starts_with("file://") || !text.contains("://")) && project::split(location).is_none()
+}
+
+// A hash of what a local file holds right now, to notice when someone else
+// changed it. `None` for other backends and files that don't exist.
+pub fn fingerprint(location: &Path) -> Option<u64> {
+    use std::hash::{Hash, Hasher};
+
+    let contents = match project::split(location) {
+        Some((path, Some(name))) => Project::open(&path, false).ok()?.raw(&name).ok()??,
+        _ if is_file(location) => {
+            let text = location.to_string_lossy();
+            std::fs::read(text.strip_prefix("file://").map_or(location, Path::new)).ok()?
+        }
+        _ => return None,
+    };
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+struct File(PathBuf);
+
+impl Backend for File {
+    fn load(&self) -> std::io::Result<Machine> {
+        crate::open(&self.0)
+    }
+
+    fn save(&self, machine: &Machine) -> std::io::Result<()> {
+        if import::reader(&self.0).is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                t!("storage.import_only"),
+            ));
+        }
+        machine_file::save(&self.0, machine)
+    }
+}
+
+// A machine in a project file, see `project`.
+struct ProjectMachine {
+    path: PathBuf,
+    name: String,
+}
+
+impl Backend for ProjectMachine {
+    fn load(&self) -> std::io::Result<Machine> {
+        Project::open(&self.path, false)?.load(&self.name)
+    }
+
+    fn save(&self, machine: &Machine) -> std::io::Result<()> {
+        Project::open(&self.path, true)?.save(&self.name, machine)
+    }
+
+    fn snapshots(&self) -> std::io::Result<Vec<(String, Snapshot)>> {
+        Project::open(&self.path, false)?.snapshots(&self.name)
+    }
+
+    fn save_snapshot(&self, name: &str, snapshot: &Snapshot) -> std::io::Result<()> {
+        Project::open(&self.path, false)?.save_snapshot(&self.name, name, &snapshot.restore())
+    }
+}
+
+// Loads with GET and saves with PUT, for a machine kept by a web service.
+// Only plain HTTP is spoken, as there is no TLS support.
+struct Http {
+    host: String,
+    address: String,
+    path: String,
+}
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl Http {
+    fn parse(rest: &str) -> std::io::Result<Self> {
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                t!("storage.no_host"),
+            ));
+        }
+
+        let address = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{host}:80"),
+        };
+        Ok(Self {
+            host: host.to_string(),
+            address,
+            path: path.to_string(),
+        })
+    }
+
+    // Requests are made while the editor waits, so a server that stopped
+    // answering gives up after `HTTP_TIMEOUT` instead of freezing it.
+    fn connect(&self) -> std::io::Result<TcpStream> {
+        let mut error = None;
+        for address in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, HTTP_TIMEOUT) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+                    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+                    return Ok(stream);
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                t!("storage.no_address", host = self.host),
+            )
+        }))
+    }
+
+    // Sends one request and returns the body of a successful response.
+    // HTTP/1.0 keeps the response free of chunked encoding.
+    fn request(&self, method: &str, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut stream = self.connect()?;
+        write!(
+            stream,
+            "{method} {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            self.path,
+            self.host,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line)?;
+        let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+        if !status.starts_with('2') {
+            return Err(std::io::Error::other(t!(
+                "storage.request_failed",
+                method = method,
+                path = self.path,
+                status = status_line.trim()
+            )));
+        }
+
+        // The headers end at the first empty line.
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+            line.clear();
+        }
+
+        let mut response = vec![];
+        reader.read_to_end(&mut response)?;
+        Ok(response)
+    }
+}
+
+impl Backend for Http {
+    fn load(&self) -> std::io::Result<Machine> {
+        let body = self.request("GET", &[])?;
+        machine_file::read(body.as_slice())
+    }
+
+    fn save(&self, machine: &Machine) -> std::io::Result<()> {
+        self.request("PUT", &machine_file::to_json(machine)?)
+            .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn parses_http_locations() {
+        let http = Http::parse("example.org:8000/machines/door.json").unwrap();
+        assert_eq!(http.host, "example.org:8000");
+        assert_eq!(http.address, "example.org:8000");
+        assert_eq!(http.path, "/machines/door.json");
+
+        let http = Http::parse("example.org").unwrap();
+        assert_eq!(http.address, "example.org:80");
+        assert_eq!(http.path, "/");
+
+        assert!(Http::parse("/door.json").is_err());
+    }
+
+    #[test]
+    fn picks_backends_by_location() {
+        let path = std::env::temp_dir().join(format!("fsmtui-{}-storage.json", std::process::id()));
+        let machine = crate::import::regex("ab").unwrap();
+        backend(&path).unwrap().save(&machine).unwrap();
+        let uri = PathBuf::from(format!("file://{}", path.display()));
+        let loaded = backend(&uri).unwrap().load();
+        std::fs::remove_file(&path).unwrap();
+        assert!(Snapshot::take(&loaded.unwrap()) == Snapshot::take(&machine));
+
+        let error = |location: &str| backend(Path::new(location)).err().unwrap().kind();
+        assert_eq!(
+            error("ftp://example.org/door.json"),
+            std::io::ErrorKind::Unsupported
+        );
+        assert_eq!(error("plans.fsmproj"), std::io::ErrorKind::InvalidInput);
+        assert_eq!(error("http:///door.json"), std::io::ErrorKind::InvalidInput);
+        assert!(backend(Path::new("plans.fsmproj#door")).is_ok());
+    }
+
+    #[test]
+    fn tells_files_apart() {
+        assert!(is_file(Path::new("door.json")));
+        assert!(is_file(Path::new("file:///tmp/door.json")));
+        assert!(!is_file(Path::new("http://example.org/door.json")));
+        assert!(!is_file(Path::new("plans.fsmproj#door")));
+    }
+
+    // Answers one request with `response` and hands back what was asked.
+    fn serve_once(response: &'static str) -> (u16, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request_line
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn loads_over_http() {
+        let (port, server) = serve_once(
+            "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"states\": [], \"initial\": null}",
+        );
+        let location = format!("http://127.0.0.1:{port}/door.json");
+        let machine = backend(Path::new(&location)).unwrap().load().unwrap();
+        assert!(machine.states.is_empty());
+        assert_eq!(server.join().unwrap(), "GET /door.json HTTP/1.0\r\n");
+
+        let (port, server) = serve_once("HTTP/1.0 404 Not Found\r\n\r\n");
+        let location = format!("http://127.0.0.1:{port}/door.json");
+        assert!(backend(Path::new(&location)).unwrap().load().is_err());
+        server.join().unwrap();
+    }
+}
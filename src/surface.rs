@@ -0,0 +1,43 @@
+use ratatui::{
+    style::Color,
+    text::Line as TextLine,
+    widgets::canvas::{Circle, Context, Line},
+};
+
+use crate::vector2d::Vector2D;
+
+// Anything the machine can be painted on, in canvas coordinates. Besides the
+// terminal canvas this is implemented by the raster used for terminal
+// graphics.
+pub trait Surface {
+    fn line(&mut self, from: Vector2D, to: Vector2D, color: Color);
+    fn circle(&mut self, center: Vector2D, radius: f64, color: Color);
+    fn print(&mut self, x: f64, y: f64, text: TextLine<'static>);
+}
+
+impl Surface for Context<'_> {
+    fn line(&mut self, from: Vector2D, to: Vector2D, color: Color) {
+        let ((x1, y1), (x2, y2)) = (from.into(), to.into());
+        self.draw(&Line {
+            x1,
+            y1,
+            x2,
+            y2,
+            color,
+        });
+    }
+
+    fn circle(&mut self, center: Vector2D, radius: f64, color: Color) {
+        let (x, y) = center.into();
+        self.draw(&Circle {
+            x,
+            y,
+            radius,
+            color,
+        });
+    }
+
+    fn print(&mut self, x: f64, y: f64, text: TextLine<'static>) {
+        Context::print(self, x, y, text);
+    }
+}
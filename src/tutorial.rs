@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use crate::App;
+
+type Check = fn(&App) -> bool;
+
+// Each step of the tutorial, as the key of its instructions and the check
+// for whether the user has done it. Steps already done are skipped, so the
+// tutorial also works on a machine that isn't empty.
+const STEPS: [(&str, Check); 9] = [
+    ("tutorial.create", |app| !app.states.is_empty()),
+    ("tutorial.second", |app| app.states.len() >= 2),
+    ("tutorial.source", |app| {
+        app.secondary_selected_state.strong_count() > 0
+    }),
+    ("tutorial.connect", |app| {
+        app.states
+            .iter()
+            .any(|s| s.borrow().next_states.iter().any(|n| n.strong_count() > 0))
+    }),
+    ("tutorial.initial", |app| {
+        app.initial_state.strong_count() > 0
+    }),
+    ("tutorial.accepting", |app| {
+        app.states.iter().any(|s| s.borrow().accepting)
+    }),
+    ("tutorial.path", |app| app.highlighted_path.len() >= 2),
+    ("tutorial.replay", |app| app.path_step.is_some()),
+    ("tutorial.save", |app| {
+        app.file_path.is_some() && !app.modified
+    }),
+];
+
+pub struct Tutorial {
+    step: usize,
+}
+
+impl Tutorial {
+    pub fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    // Moves past every step that is done. Returns false once all are.
+    pub fn advance(&mut self, app: &App) -> bool {
+        while let Some((_, done)) = STEPS.get(self.step) {
+            if !done(app) {
+                return true;
+            }
+            self.step += 1;
+        }
+        false
+    }
+
+    pub fn instructions(&self) -> String {
+        let key = STEPS.get(self.step).map_or("tutorial.done", |(key, _)| key);
+        t!(
+            "tutorial.step",
+            step = self.step + 1,
+            count = STEPS.len(),
+            text = crate::i18n::text(key, &[])
+        )
+    }
+}
+
+// Left in the config directory once the tutorial has been offered, so it only
+// starts by itself on the very first run.
+fn marker() -> Option<PathBuf> {
+    crate::Config::default_path().and_then(|p| p.parent().map(|dir| dir.join("tutorial-seen")))
+}
+
+// Whether this is the first run, marking it as seen so it isn't offered
+// again. Without a config directory nothing can be remembered, and the
+// tutorial isn't offered at all.
+pub fn first_run() -> bool {
+    let Some(marker) = marker() else {
+        return false;
+    };
+    if marker.exists() {
+        return false;
+    }
+
+    marker
+        .parent()
+        .is_some_and(|dir| std::fs::create_dir_all(dir).is_ok())
+        && std::fs::write(&marker, "").is_ok()
+}
@@ -0,0 +1,1145 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::rc::{Rc, Weak};
+
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line as TextLine, Text},
+    widgets::{canvas::Canvas, Block, Clear, List, ListState, Paragraph, Widget, Wrap},
+    DefaultTerminal, Frame,
+};
+use unicode_width::UnicodeWidthStr;
+
+use crate::config::LabelPosition;
+use crate::machine_file::Snapshot;
+use crate::surface::Surface;
+use crate::vector2d::Vector2D;
+use crate::{
+    analysis, diagnostics, draw_arrow, graphics, App, FSMState, PromptKind, Pseudo, Split,
+};
+
+// States by address, to look them up once per state while painting.
+type StateSet = HashSet<*const RefCell<FSMState>>;
+
+fn pointers(states: &[Rc<RefCell<FSMState>>]) -> StateSet {
+    states.iter().map(Rc::as_ptr).collect()
+}
+
+// Why a state stands out on the canvas, in order of precedence.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Highlight {
+    Selected,
+    ConnectionSource,
+    OnPath,
+    Deadlock,
+    CannotAccept,
+    None,
+}
+
+impl Highlight {
+    fn color(self) -> Color {
+        match self {
+            Self::Selected => Color::Yellow,
+            Self::ConnectionSource => Color::Cyan,
+            Self::OnPath => Color::Green,
+            Self::Deadlock => Color::Red,
+            Self::CannotAccept => Color::Magenta,
+            Self::None => Color::White,
+        }
+    }
+
+    // Printed next to the state in monochrome mode, where colors can't be
+    // told apart.
+    fn tag(self) -> &'static str {
+        match self {
+            Self::Selected => "*sel",
+            Self::ConnectionSource => "*src",
+            Self::OnPath => "*path",
+            Self::Deadlock => "!dead",
+            Self::CannotAccept => "!rej",
+            Self::None => "",
+        }
+    }
+}
+
+// Rendering, kept apart from `update` and the rest of the model code.
+impl App {
+    // Draws the model to the terminal, and to the other places it is shown.
+    pub fn view(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        let start = std::time::Instant::now();
+        terminal.draw(|frame| self.draw(frame))?;
+        self.frame_time = start.elapsed();
+        self.needs_redraw = false;
+
+        // Pulses and other changes that only show in the terminal don't need
+        // a new SVG.
+        if let Some(web_view) = self.web_view.as_ref().filter(|_| self.web_view_outdated) {
+            self.web_view_outdated = false;
+            web_view.update(
+                &self.states,
+                &self.initial_state,
+                (self.config.x_bounds, self.config.y_bounds),
+                &self.config.states,
+                &self.config.edges,
+                &self.metadata,
+            );
+        }
+
+        let shown = if self.graphics {
+            self.show_graphics()
+        } else {
+            Ok(())
+        };
+        if let Err(e) = shown {
+            self.graphics = false;
+            self.report(t!("error.graphics", error = e));
+        }
+
+        Ok(())
+    }
+
+    // Overlays are drawn as text, which the image would show through, so it
+    // is taken down while one is open.
+    fn show_graphics(&self) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout();
+        let area = self.canvas_area.get();
+        let covered =
+            self.list_mode || self.file_browser.is_some() || self.show_perf || self.error.is_some();
+
+        match graphics::cell_size() {
+            Some((cell_width, cell_height)) if !covered && !area.is_empty() => {
+                let mut raster = graphics::Raster::new(
+                    area.width as u32 * cell_width,
+                    area.height as u32 * cell_height,
+                    self.x_bounds(),
+                    self.y_bounds(),
+                );
+                self.paint(&mut raster);
+                if self.show_rulers {
+                    self.paint_rulers(&mut raster);
+                }
+                raster.show(&mut stdout, area)
+            }
+            _ => {
+                graphics::clear(&mut stdout)?;
+                stdout.flush()
+            }
+        }
+    }
+
+    fn is_highlighted_edge(&self, from: &FSMState, to: &FSMState) -> bool {
+        let shown_edges = self.path_step.unwrap_or(usize::MAX);
+
+        self.highlighted_path
+            .windows(2)
+            .take(shown_edges)
+            .any(|pair| {
+                let (Some(a), Some(b)) = (pair[0].upgrade(), pair[1].upgrade()) else {
+                    return false;
+                };
+
+                std::ptr::eq(a.as_ptr(), from) && std::ptr::eq(b.as_ptr(), to)
+            })
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        if self.presenting {
+            self.canvas_area.set(frame.area());
+            frame.render_widget(self.canvas(), frame.area());
+            self.error_popup(frame);
+            return;
+        }
+
+        let help = if self.replay.is_some() {
+            t!("help.replay")
+        } else if self.confirm_quit {
+            t!("help.confirm_quit")
+        } else if let Some(path) = &self.overwrite {
+            t!("help.overwrite", path = path.display())
+        } else if let Some(replacement) = &self.replacement {
+            t!("help.replace", count = replacement.preview.len())
+        } else if let Some(split) = &self.split {
+            t!("help.split", name = split.name)
+        } else if let Some(prompt) = &self.prompt {
+            match prompt.kind {
+                PromptKind::Property => {
+                    t!("help.property")
+                }
+                PromptKind::Command => {
+                    t!("help.command")
+                }
+                PromptKind::Chain => {
+                    t!("help.chain")
+                }
+            }
+        } else if self.new_state_name.is_some() {
+            t!("help.new_state")
+        } else if let Some(tutorial) = &self.tutorial {
+            tutorial.instructions()
+        } else if self.show_help {
+            t!("help.main")
+        } else {
+            self.hints()
+        };
+
+        // The menu takes as many lines as its text needs, leaving the rest to
+        // the canvas. The full list of keys may take up to three quarters.
+        let area = frame.area();
+        let limit = if self.show_help {
+            area.height * 3 / 4
+        } else {
+            area.height / 2
+        };
+        let menu_height = (help.lines().count() as u16 + 2).min(limit);
+        let vertical = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(menu_height),
+            Constraint::Length(1),
+        ]);
+        let [canvas, menu, announcement] = vertical.areas(area);
+
+        let mut panels = vec![];
+        if self.show_diagnostics {
+            panels.push(self.diagnostics());
+        }
+        if self.show_properties {
+            panels.push(self.properties_panel());
+        }
+        if self.show_history {
+            panels.push(self.history_panel());
+        }
+        if self.show_metadata {
+            panels.push(self.metadata_panel());
+        }
+        if let Some(name) = &self.diff_against {
+            panels.push(self.diff_panel(name));
+        }
+        if !self.highlighted_path.is_empty() {
+            panels.push(self.path_panel());
+        }
+        if let Some(split) = &self.split {
+            panels.push(self.split_panel(split));
+        }
+        if let Some(replacement) = &self.replacement {
+            let lines: Vec<TextLine> = replacement
+                .preview
+                .iter()
+                .map(|line| TextLine::from(line.clone()))
+                .collect();
+            panels.push(Paragraph::new(lines).block(Block::bordered().title(t!(
+                "panel.replace.title",
+                find = replacement.find,
+                with = format!("{:?}", replacement.with)
+            ))));
+        }
+
+        let canvas = if panels.is_empty() {
+            canvas
+        } else {
+            let [canvas, side] =
+                Layout::horizontal([Constraint::Percentage(75), Constraint::Percentage(25)])
+                    .areas(canvas);
+            let areas = Layout::vertical(vec![Constraint::Fill(1); panels.len()]).split(side);
+
+            for (panel, area) in panels.into_iter().zip(areas.iter()) {
+                frame.render_widget(panel, *area);
+            }
+            canvas
+        };
+
+        self.canvas_area.set(canvas);
+        if self.list_mode {
+            self.list_view(frame, canvas);
+        } else {
+            frame.render_widget(self.canvas(), canvas);
+        }
+
+        frame.render_widget(
+            Paragraph::new(help).block(Block::bordered().title(self.menu_title())),
+            menu,
+        );
+        let announcement_text = match &self.prompt {
+            Some(prompt) if prompt.kind == PromptKind::Command => format!(":{}", prompt.input),
+            Some(prompt) => format!("> {}", prompt.input),
+            None => self.announcement(),
+        };
+        frame.render_widget(Paragraph::new(announcement_text), announcement);
+
+        if self.show_perf {
+            let [_, overlay] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(26)]).areas(canvas);
+            let [overlay, _] =
+                Layout::vertical([Constraint::Length(6), Constraint::Fill(1)]).areas(overlay);
+
+            frame.render_widget(Clear, overlay);
+            frame.render_widget(self.perf_overlay(), overlay);
+        }
+
+        if let Some(browser) = &self.file_browser {
+            browser.render(frame, frame.area());
+        }
+        self.error_popup(frame);
+    }
+
+    fn error_popup(&self, frame: &mut Frame) {
+        let Some(error) = &self.error else {
+            return;
+        };
+
+        let [_, area, _] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Percentage(50),
+            Constraint::Fill(1),
+        ])
+        .areas(frame.area());
+        let [_, area, _] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(6),
+            Constraint::Fill(1),
+        ])
+        .areas(area);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(vec![
+                TextLine::from(error.clone()),
+                TextLine::from(""),
+                TextLine::from(t!("error.continue")).italic(),
+            ])
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(t!("error.title")).red()),
+            area,
+        );
+    }
+
+    // The outcome of the last action followed by what is selected, kept on a
+    // single line at the bottom so screen readers can follow along without
+    // making sense of the canvas.
+    fn announcement(&self) -> String {
+        let selection = match self.selected_state.upgrade() {
+            Some(state) => {
+                let incoming = self
+                    .states
+                    .iter()
+                    .filter(|s| {
+                        s.borrow()
+                            .next_states
+                            .iter()
+                            .any(|n| std::ptr::eq(n.as_ptr(), Rc::as_ptr(&state)))
+                    })
+                    .count();
+
+                let is_initial = std::ptr::eq(self.initial_state.as_ptr(), Rc::as_ptr(&state));
+                let state = state.borrow();
+                let mut text = t!(
+                    "announce.selected",
+                    name = state.name,
+                    outgoing = state.next_states.len(),
+                    incoming = incoming
+                );
+                if is_initial {
+                    text += &t!("announce.initial");
+                }
+                if state.accepting {
+                    text += &t!("announce.accepting");
+                }
+                match state.pseudo {
+                    Some(Pseudo::Choice) => text += &t!("announce.choice"),
+                    Some(Pseudo::Junction) => text += &t!("announce.junction"),
+                    None => {}
+                }
+                text += &t!(
+                    "announce.position",
+                    x = format!("{:.0}", state.x),
+                    y = format!("{:.0}", state.y)
+                );
+                text + "."
+            }
+            None => t!("announce.none_selected", count = self.states.len()),
+        };
+
+        let selection = match self.secondary_selected_state.upgrade() {
+            Some(source) => {
+                selection + " " + &t!("announce.connecting", name = source.borrow().name)
+            }
+            None => selection,
+        };
+
+        let selection = match self.cursor_point {
+            Some(point) => {
+                selection
+                    + " "
+                    + &t!(
+                        "announce.cursor",
+                        x = format!("{:.0}", point.x),
+                        y = format!("{:.0}", point.y)
+                    )
+            }
+            None => selection,
+        };
+
+        match &self.status {
+            Some(status) => format!("{status} {selection}"),
+            None => selection,
+        }
+    }
+
+    // A few lines on what can be done right now: with the selected state,
+    // with a transition from the connection source, and in the open panels.
+    fn hints(&self) -> String {
+        let mut lines = vec![];
+        if self.list_mode {
+            lines.push(t!("hint.list"));
+        }
+        if self.show_history {
+            lines.push(t!("hint.history"));
+        }
+        if self.show_diagnostics {
+            lines.push(t!("hint.diagnostics"));
+        }
+        if self.show_properties {
+            lines.push(t!("hint.properties"));
+        }
+
+        let source = self.secondary_selected_state.upgrade();
+        let selected = self.selected_state.upgrade();
+        lines.push(match (source, selected) {
+            (Some(source), Some(target)) => t!(
+                "hint.transition",
+                from = source.borrow().name,
+                to = target.borrow().name
+            ),
+            (Some(source), None) => t!("hint.source", name = source.borrow().name),
+            (None, Some(state)) => t!("hint.state", name = state.borrow().name),
+            (None, None) => t!("hint.idle"),
+        });
+
+        lines.join("\n")
+    }
+
+    fn menu_title(&self) -> String {
+        let name = self
+            .file_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map_or(t!("menu.untitled"), |n| n.to_string_lossy().into_owned());
+
+        let replay = match &self.replay {
+            Some(replay) => t!("menu.replaying", keys = replay.remaining()),
+            None => String::new(),
+        };
+
+        let recording = match self.macros.recording() {
+            Some(register) => t!("menu.recording", register = register),
+            None => String::new(),
+        };
+
+        let serving = match &self.web_view {
+            Some(web_view) => t!("menu.serving", port = web_view.port),
+            None => String::new(),
+        };
+
+        let modified = match self.modified {
+            true => t!("menu.modified"),
+            false => String::new(),
+        };
+        let read_only = match self.read_only {
+            true => t!("menu.read_only"),
+            false => String::new(),
+        };
+
+        t!("menu.title", name = name) + &modified + &read_only + &recording + &replay + &serving
+    }
+
+    fn list_view(&self, frame: &mut Frame, area: Rect) {
+        let is = |weak: &Weak<RefCell<FSMState>>, state: &Rc<RefCell<FSMState>>| {
+            std::ptr::eq(weak.as_ptr(), Rc::as_ptr(state))
+        };
+        let name = |weak: &Weak<RefCell<FSMState>>| {
+            weak.upgrade()
+                .map_or(t!("panel.deleted"), |s| s.borrow().name.clone())
+        };
+
+        let items: Vec<String> = self
+            .states
+            .iter()
+            .map(|state| {
+                let mut notes = vec![];
+                if is(&self.initial_state, state) {
+                    notes.push(t!("panel.list.initial"));
+                }
+                if state.borrow().accepting {
+                    notes.push(t!("panel.list.accepting"));
+                }
+                match state.borrow().pseudo {
+                    Some(Pseudo::Choice) => notes.push(t!("panel.list.choice")),
+                    Some(Pseudo::Junction) => notes.push(t!("panel.list.junction")),
+                    None => {}
+                }
+                if is(&self.secondary_selected_state, state) {
+                    notes.push(t!("panel.list.source"));
+                }
+                notes.push(t!(
+                    "panel.list.outgoing",
+                    count = state.borrow().next_states.len()
+                ));
+
+                format!("{} ({})", state.borrow().name, notes.join(", "))
+            })
+            .collect();
+
+        let selected = self.selected_state.upgrade();
+        let selected_index = selected
+            .as_ref()
+            .and_then(|sel| self.states.iter().position(|s| Rc::ptr_eq(s, sel)));
+
+        let transitions: Vec<String> = match &selected {
+            Some(selected) => {
+                let with_note = |text: String, note: Option<&str>| match note {
+                    Some(note) => format!("{text}: {note}"),
+                    None => text,
+                };
+                let outgoing = selected
+                    .borrow()
+                    .next_states
+                    .iter()
+                    .map(|next| {
+                        with_note(
+                            t!("panel.list.to", name = name(next)),
+                            selected.borrow().note(next.as_ptr()),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let incoming = self
+                    .states
+                    .iter()
+                    .filter(|s| s.borrow().next_states.iter().any(|n| is(n, selected)))
+                    .map(|s| {
+                        let s = s.borrow();
+                        with_note(
+                            t!("panel.list.from", name = s.name),
+                            s.note(Rc::as_ptr(selected)),
+                        )
+                    });
+
+                outgoing.into_iter().chain(incoming).collect()
+            }
+            None => vec![t!("panel.list.select")],
+        };
+
+        let [states_area, transitions_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(area);
+
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(t!("panel.list.states", count = self.states.len())))
+                .highlight_style(Style::new().reversed()),
+            states_area,
+            &mut ListState::default().with_selected(selected_index),
+        );
+        frame.render_widget(
+            List::new(transitions).block(Block::bordered().title(t!("panel.list.transitions"))),
+            transitions_area,
+        );
+    }
+
+    fn path_panel(&self) -> Paragraph<'static> {
+        let names: Vec<String> = self
+            .highlighted_path
+            .iter()
+            .map(|s| match s.upgrade() {
+                Some(s) => s.borrow().name.clone(),
+                None => t!("panel.deleted"),
+            })
+            .collect();
+
+        Paragraph::new(format!(
+            "{}\n\n{}",
+            names.join(" -> "),
+            t!("panel.path.length", count = names.len() - 1)
+        ))
+        .wrap(Wrap { trim: false })
+        .block(Block::bordered().title(t!("panel.path.title")))
+    }
+
+    fn diagnostics(&self) -> Paragraph<'static> {
+        let issues = diagnostics::collect(&self.states, &self.initial_state, &self.properties);
+
+        let text = if issues.is_empty() {
+            Text::from(t!("panel.diagnostics.none"))
+        } else {
+            issues
+                .into_iter()
+                .enumerate()
+                .map(|(i, issue)| {
+                    if Some(i) == self.issue_cursor {
+                        TextLine::from(issue.message).reversed()
+                    } else {
+                        TextLine::from(issue.message)
+                    }
+                })
+                .collect()
+        };
+
+        Paragraph::new(text)
+            .block(Block::bordered().title(t!("panel.diagnostics.title")))
+            .wrap(Wrap { trim: false })
+    }
+
+    fn properties_panel(&self) -> Paragraph<'static> {
+        let mut lines: Vec<TextLine> = self
+            .properties
+            .iter()
+            .enumerate()
+            .map(|(i, property)| {
+                let line = match property.check(&self.states) {
+                    Ok(()) => {
+                        TextLine::from(format!("{} {property}", t!("panel.properties.ok"))).green()
+                    }
+                    Err(_) => {
+                        TextLine::from(format!("{} {property}", t!("panel.properties.failed")))
+                            .red()
+                    }
+                };
+
+                if i == self.property_cursor {
+                    line.reversed()
+                } else {
+                    line
+                }
+            })
+            .collect();
+
+        if lines.is_empty() {
+            lines.push(TextLine::from(t!("panel.properties.none")));
+        }
+
+        Paragraph::new(lines).block(Block::bordered().title(t!("panel.properties.title")))
+    }
+
+    fn metadata_panel(&self) -> Paragraph<'static> {
+        let field = |label: String, value: &str| {
+            let value = match value.is_empty() {
+                true => t!("panel.details.none").gray(),
+                false => value.to_string().into(),
+            };
+            TextLine::from(vec![format!("{label}: ").bold(), value])
+        };
+
+        Paragraph::new(vec![
+            field(t!("panel.details.document_title"), &self.metadata.title),
+            field(t!("panel.details.author"), &self.metadata.author),
+            field(t!("panel.details.description"), &self.metadata.description),
+        ])
+        .wrap(Wrap { trim: false })
+        .block(Block::bordered().title(t!("panel.details.title")))
+    }
+
+    fn diff_panel(&self, name: &str) -> Paragraph<'static> {
+        let changes = match self.find_snapshot(name) {
+            Some(snapshot) => snapshot.changes(&Snapshot::take(&self.machine())),
+            None => vec![],
+        };
+
+        let lines: Vec<TextLine> = match changes.is_empty() {
+            true => vec![TextLine::from(t!("panel.diff.none"))],
+            false => changes.into_iter().map(TextLine::from).collect(),
+        };
+
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(t!("panel.diff.title", name = name)))
+    }
+
+    fn history_panel(&self) -> Paragraph<'static> {
+        let lines: Vec<TextLine> = self
+            .history
+            .entries()
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let age = match entry.age.as_secs() {
+                    s if s < 60 => format!("{s}s"),
+                    s if s < 3600 => format!("{}m", s / 60),
+                    s => format!("{}h", s / 3600),
+                };
+                let line = TextLine::from(format!(
+                    "{}{} {age:>3} {}",
+                    "  ".repeat(entry.depth),
+                    if entry.current { "*" } else { " " },
+                    entry.label
+                ));
+
+                match (i == self.history_cursor, entry.current) {
+                    (true, _) => line.reversed(),
+                    (false, true) => line.yellow(),
+                    (false, false) => line,
+                }
+            })
+            .collect();
+
+        Paragraph::new(lines).block(Block::bordered().title(t!("panel.history.title")))
+    }
+
+    fn split_panel(&self, split: &Split) -> Paragraph<'static> {
+        let lines: Vec<TextLine> = if split.incoming.is_empty() {
+            vec![TextLine::from(t!("panel.split.none"))]
+        } else {
+            split
+                .incoming
+                .iter()
+                .enumerate()
+                .map(|(i, (from, moves))| {
+                    let name = from
+                        .upgrade()
+                        .map_or(t!("panel.deleted"), |s| s.borrow().name.clone());
+                    let mark = if *moves { "x" } else { " " };
+                    let line = TextLine::from(t!("panel.split.from", mark = mark, name = name));
+                    match i == split.cursor {
+                        true => line.reversed(),
+                        false => line,
+                    }
+                })
+                .collect()
+        };
+
+        Paragraph::new(lines)
+            .block(Block::bordered().title(t!("panel.split.title", name = split.name)))
+    }
+
+    fn perf_overlay(&self) -> impl Widget {
+        let edge_count: usize = self
+            .states
+            .iter()
+            .map(|s| {
+                s.borrow()
+                    .next_states
+                    .iter()
+                    .filter(|n| n.strong_count() > 0)
+                    .count()
+            })
+            .sum();
+
+        Paragraph::new(t!(
+            "panel.perf.body",
+            time = format!("{:.2}", self.frame_time.as_secs_f64() * 1000.0),
+            calls = self.draw_calls.get(),
+            states = self.states.len(),
+            edges = edge_count,
+        ))
+        .block(Block::bordered().title(t!("panel.perf.title")))
+    }
+
+    // The deadlocks and the states that cannot accept, when the diagnostics
+    // panel is open to show them.
+    fn flagged_states(&self) -> (StateSet, StateSet) {
+        if !self.show_diagnostics {
+            return (StateSet::new(), StateSet::new());
+        }
+        (
+            pointers(&analysis::deadlocks(&self.states)),
+            pointers(&analysis::cannot_accept(&self.states)),
+        )
+    }
+
+    fn state_highlight(
+        &self,
+        state: &Rc<RefCell<FSMState>>,
+        deadlocks: &StateSet,
+        cannot_accept: &StateSet,
+    ) -> Highlight {
+        let is = |other: &Weak<RefCell<FSMState>>| std::ptr::eq(other.as_ptr(), Rc::as_ptr(state));
+
+        if self.selected_state.strong_count() > 0 && is(&self.selected_state) {
+            Highlight::Selected
+        } else if self.secondary_selected_state.strong_count() > 0
+            && is(&self.secondary_selected_state)
+        {
+            Highlight::ConnectionSource
+        } else if self
+            .path_step
+            .is_some_and(|step| is(&self.highlighted_path[step]))
+            || self.spotlight.iter().any(is)
+        {
+            Highlight::OnPath
+        } else if deadlocks.contains(&Rc::as_ptr(state)) {
+            Highlight::Deadlock
+        } else if cannot_accept.contains(&Rc::as_ptr(state)) {
+            Highlight::CannotAccept
+        } else {
+            Highlight::None
+        }
+    }
+
+    // Paints the machine in canvas coordinates, returning the number of
+    // shapes drawn.
+    fn paint(&self, surface: &mut impl Surface) -> usize {
+        if self.clustered {
+            return self.paint_clusters(surface);
+        }
+
+        let mut draw_calls = 0;
+        let (deadlocks, cannot_accept) = self.flagged_states();
+
+        // Isolated states are outlined dashed whether or not the
+        // diagnostics panel is open, since they're easy to lose track
+        // of in a big diagram.
+        let orphans = pointers(&analysis::orphans(&self.states));
+        let states = self.visible_states();
+        let shown: HashSet<*const FSMState> =
+            states.iter().map(|s| s.as_ptr().cast_const()).collect();
+        let visible = |s: &FSMState| shown.contains(&std::ptr::from_ref(s));
+
+        let plain = if self.monochrome {
+            Color::Reset
+        } else {
+            Color::White
+        };
+
+        for state in &states {
+            let highlight = self.state_highlight(state, &deadlocks, &cannot_accept);
+
+            draw_calls += state.borrow().draw(
+                if self.monochrome {
+                    plain
+                } else {
+                    highlight.color()
+                },
+                orphans.contains(&Rc::as_ptr(state)),
+                |next| match (
+                    visible(next),
+                    self.is_highlighted_edge(&state.borrow(), next),
+                    self.monochrome,
+                ) {
+                    (false, _, _) => None,
+                    (true, true, true) => Some((plain, true)),
+                    (true, true, false) => Some((Color::Green, false)),
+                    (true, false, _) => Some((plain, false)),
+                },
+                &self.config.edges,
+                &self.config.states,
+                surface,
+            );
+
+            if self.monochrome && highlight != Highlight::None {
+                let state = state.borrow();
+                let radius = state.circle_radius(&self.config.states);
+                let tag = highlight.tag();
+                // Under the name when that is drawn below the circle too.
+                let below = match self.config.states.label {
+                    LabelPosition::Below => radius + 24.0,
+                    _ => radius * 1.5,
+                };
+                surface.print(state.x - tag.len() as f64, state.y - below, tag.into());
+                draw_calls += 1;
+            }
+
+            if self.pulse_on()
+                && matches!(highlight, Highlight::Selected | Highlight::ConnectionSource)
+            {
+                let state = state.borrow();
+                let color = if self.monochrome {
+                    plain
+                } else {
+                    highlight.color()
+                };
+                surface.circle(
+                    state.position(),
+                    state.circle_radius(&self.config.states) + 4.0,
+                    color,
+                );
+                draw_calls += 1;
+            }
+        }
+
+        if self.moving {
+            draw_calls += self.paint_guides(&states, surface);
+        }
+
+        let initial = self
+            .initial_state
+            .upgrade()
+            .filter(|i| visible(&i.borrow()));
+        if let Some(initial) = initial {
+            let initial = initial.borrow();
+            let end = initial.position()
+                - Vector2D {
+                    x: initial.circle_radius(&self.config.states) * 1.5,
+                    y: 0.0,
+                };
+
+            draw_arrow(
+                surface,
+                end - Vector2D { x: 20.0, y: 0.0 },
+                end,
+                plain,
+                &self.config.edges.normal,
+            );
+            draw_calls += 3;
+        }
+
+        let initial = self.initial_state.upgrade().filter(|_| self.show_depths);
+        if let Some(initial) = initial {
+            let depths = analysis::depths(&initial);
+
+            for state in &states {
+                let Some(depth) = depths.get(&Rc::as_ptr(state)) else {
+                    continue;
+                };
+
+                let state = state.borrow();
+                surface.print(
+                    state.x,
+                    state.y + state.circle_radius(&self.config.states) * 1.5,
+                    depth.to_string().gray().into(),
+                );
+                draw_calls += 1;
+            }
+        }
+
+        if let Some(new_state_name) = &self.new_state_name {
+            surface.print(0.0, 0.0, new_state_name.clone().into());
+        }
+
+        draw_calls
+    }
+
+    // The states the canvas shows: all of them, or only those on paths
+    // between the ends of the path filter while it is on and both ends exist.
+    fn visible_states(&self) -> Vec<Rc<RefCell<FSMState>>> {
+        let ends = self
+            .path_filter
+            .as_ref()
+            .and_then(|[from, to]| Some((from.upgrade()?, to.upgrade()?)));
+
+        match ends {
+            Some((from, to)) => analysis::between(&self.states, &from, &to),
+            None => self.states.clone(),
+        }
+    }
+
+    // Paints every strongly connected component as one node at the middle
+    // of its states, named after its first state and how many more it
+    // holds, with one arrow for all transitions between two components.
+    fn paint_clusters(&self, surface: &mut impl Surface) -> usize {
+        let mut draw_calls = 0;
+        let states = self.visible_states();
+        let components = analysis::components(&states);
+        let (deadlocks, cannot_accept) = self.flagged_states();
+
+        let mut component_of = HashMap::new();
+        let nodes: Vec<(Vector2D, f64)> = components
+            .iter()
+            .enumerate()
+            .map(|(i, members)| {
+                let mut center = Vector2D { x: 0.0, y: 0.0 };
+                for state in members {
+                    component_of.insert(Rc::as_ptr(state), i);
+                    center = center + state.borrow().position();
+                }
+                center = center * (1.0 / members.len() as f64);
+
+                let radius = match members.as_slice() {
+                    [state] => state.borrow().circle_radius(&self.config.states),
+                    _ => self.config.states.radius * 2.0,
+                };
+                (center, radius)
+            })
+            .collect();
+
+        let mut edges = HashSet::new();
+        for state in &states {
+            let from = component_of[&Rc::as_ptr(state)];
+            for next in &state.borrow().next_states {
+                if let Some(&to) = component_of.get(&next.as_ptr()) {
+                    if from != to {
+                        edges.insert((from, to));
+                    }
+                }
+            }
+        }
+
+        let plain = if self.monochrome {
+            Color::Reset
+        } else {
+            Color::White
+        };
+
+        for &(from, to) in &edges {
+            let ((v1, r1), (v2, r2)) = (nodes[from], nodes[to]);
+            let direction = (v2 - v1).normalized() * 1.5;
+            draw_arrow(
+                surface,
+                v1 + direction * r1,
+                v2 - direction * r2,
+                plain,
+                &self.config.edges.normal,
+            );
+            draw_calls += 3;
+        }
+
+        for (members, &(center, radius)) in components.iter().zip(&nodes) {
+            let highlight = members
+                .iter()
+                .map(|s| self.state_highlight(s, &deadlocks, &cannot_accept))
+                .min()
+                .unwrap_or(Highlight::None);
+            let color = if self.monochrome {
+                plain
+            } else {
+                highlight.color()
+            };
+
+            surface.circle(center, radius, color);
+            let first = members[0].borrow();
+            let label = match members.len() {
+                1 => first.name.clone(),
+                n => format!("{} +{}", first.name, n - 1),
+            };
+            // Components are ringed twice, like accepting states, since
+            // a lone accepting state is drawn like one.
+            if members.len() > 1 || first.accepting {
+                surface.circle(center, radius - 3.0, color);
+                draw_calls += 1;
+            }
+            surface.print(
+                center.x - label.width() as f64 + 1.0,
+                center.y - 5.0,
+                label.into(),
+            );
+            draw_calls += 2;
+
+            if self.monochrome && highlight != Highlight::None {
+                let tag = highlight.tag();
+                surface.print(
+                    center.x - tag.len() as f64,
+                    center.y - radius * 1.5,
+                    tag.into(),
+                );
+                draw_calls += 1;
+            }
+        }
+
+        let initial = self
+            .initial_state
+            .upgrade()
+            .and_then(|i| component_of.get(&Rc::as_ptr(&i)).copied());
+        if let Some(initial) = initial {
+            let (center, radius) = nodes[initial];
+            let end = center
+                - Vector2D {
+                    x: radius * 1.5,
+                    y: 0.0,
+                };
+            draw_arrow(
+                surface,
+                end - Vector2D { x: 20.0, y: 0.0 },
+                end,
+                plain,
+                &self.config.edges.normal,
+            );
+            draw_calls += 3;
+        }
+
+        draw_calls
+    }
+
+    // Lines from the selected state to every state it lines up with,
+    // horizontally or vertically.
+    fn paint_guides(&self, states: &[Rc<RefCell<FSMState>>], surface: &mut impl Surface) -> usize {
+        let Some(selected) = self.selected_state.upgrade() else {
+            return 0;
+        };
+        let color = if self.monochrome {
+            Color::Reset
+        } else {
+            Color::Blue
+        };
+        let position = selected.borrow().position();
+        let mut draw_calls = 0;
+
+        for other in states.iter().filter(|s| !Rc::ptr_eq(s, &selected)) {
+            let other = other.borrow().position();
+            if (other.x - position.x).abs() < 0.5 || (other.y - position.y).abs() < 0.5 {
+                surface.line(position, other, color);
+                draw_calls += 1;
+            }
+        }
+
+        draw_calls
+    }
+
+    // Scales along the bottom and left edges of the canvas, with a tick and
+    // a coordinate every round number of units.
+    fn paint_rulers(&self, surface: &mut impl Surface) -> usize {
+        let ([x0, x1], [y0, y1]) = (self.x_bounds(), self.y_bounds());
+        let color = if self.monochrome {
+            Color::Reset
+        } else {
+            Color::Gray
+        };
+        let (tick_x, tick_y) = ((x1 - x0) / 100.0, (y1 - y0) / 50.0);
+        let mut draw_calls = 2;
+
+        surface.line(Vector2D { x: x0, y: y0 }, Vector2D { x: x1, y: y0 }, color);
+        surface.line(Vector2D { x: x0, y: y0 }, Vector2D { x: x0, y: y1 }, color);
+
+        let step = ruler_step(x1 - x0);
+        let mut x = (x0 / step).ceil() * step;
+        while x <= x1 {
+            surface.line(Vector2D { x, y: y0 }, Vector2D { x, y: y0 + tick_y }, color);
+            surface.print(x, y0 + tick_y * 2.0, format!("{x:.0}").fg(color).into());
+            draw_calls += 2;
+            x += step;
+        }
+
+        let step = ruler_step(y1 - y0);
+        let mut y = (y0 / step).ceil() * step;
+        while y <= y1 {
+            surface.line(Vector2D { x: x0, y }, Vector2D { x: x0 + tick_x, y }, color);
+            surface.print(x0 + tick_x * 2.0, y, format!("{y:.0}").fg(color).into());
+            draw_calls += 2;
+            y += step;
+        }
+
+        draw_calls
+    }
+
+    fn canvas(&self) -> impl Widget + '_ {
+        Canvas::default()
+            .marker(self.marker)
+            .paint(|ctx| {
+                let mut draw_calls = if self.graphics {
+                    self.paint(&mut graphics::LabelsOnly(ctx))
+                } else {
+                    self.paint(ctx)
+                };
+                if self.show_rulers {
+                    draw_calls += if self.graphics {
+                        self.paint_rulers(&mut graphics::LabelsOnly(ctx))
+                    } else {
+                        self.paint_rulers(ctx)
+                    };
+                }
+                self.draw_calls.set(draw_calls);
+            })
+            .x_bounds(self.x_bounds())
+            .y_bounds(self.y_bounds())
+    }
+}
+
+// A round distance between ruler ticks, 1, 2 or 5 times a power of ten,
+// that fits about eight ticks into `span`.
+fn ruler_step(span: f64) -> f64 {
+    let rough = span / 8.0;
+    let power = 10f64.powf(rough.log10().floor());
+
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * power)
+        .find(|step| *step >= rough)
+        .unwrap_or(power * 10.0)
+}
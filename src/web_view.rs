@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{EdgeStyles, StateStyle};
+use crate::export;
+use crate::machine_file::Metadata;
+use crate::FSMState;
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>fsmtui</title></head>
+<body style="margin:0;background:#111">
+<div id="machine"></div>
+<script>
+// Reconnects by itself when the editor is restarted.
+const events = new EventSource("/events" + location.search);
+events.onmessage = (event) => {
+    document.getElementById("machine").innerHTML = event.data;
+};
+</script>
+</body>
+</html>
+"#;
+
+// Clients that stop reading or never finish their request are dropped
+// after this long, so they can't pile up threads.
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Open event streams get a comment this often, which finds out when the
+// browser went away.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// Read-only view of the machine for browsers. The editor hands over a
+// freshly rendered SVG whenever the machine changed, and pages are sent the
+// ones that differ as server-sent events. Adding `?legend` to either address adds a
+// key explaining the notation, for images that are passed around.
+pub struct WebView {
+    shared: Arc<Shared>,
+    pub port: u16,
+}
+
+#[derive(Default)]
+struct Shared {
+    rendered: Mutex<Rendered>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Rendered {
+    svg: String,
+    legend: String,
+    // Counts the changes, so event streams know what they already sent.
+    version: u64,
+}
+
+impl WebView {
+    // Serves only this computer unless given another address, like
+    // `0.0.0.0:8000` to show the machine to the whole network.
+    pub fn serve(address: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        // Asking for port 0 gets whichever one is free.
+        let port = listener.local_addr()?.port();
+        let shared = Arc::new(Shared::default());
+
+        let served = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().filter_map(Result::ok) {
+                let shared = served.clone();
+                thread::spawn(move || {
+                    // The browser just retries on errors.
+                    let _ = respond(stream, &shared);
+                });
+            }
+        });
+
+        Ok(Self { shared, port })
+    }
+
+    pub fn update(
+        &self,
+        states: &[Rc<RefCell<FSMState>>],
+        initial: &Weak<RefCell<FSMState>>,
+        bounds: ([f64; 2], [f64; 2]),
+        style: &StateStyle,
+        edges: &EdgeStyles,
+        metadata: &Metadata,
+    ) {
+        let svg = export::svg(states, initial, bounds, style, edges, metadata);
+        let legend = export::svg_legend(states, initial, bounds);
+
+        if let Ok(mut rendered) = self.shared.rendered.lock() {
+            if rendered.svg != svg || rendered.legend != legend {
+                rendered.version += 1;
+                (rendered.svg, rendered.legend) = (svg, legend);
+                self.shared.changed.notify_all();
+            }
+        }
+    }
+}
+
+fn svg(rendered: &Rendered, legend: bool) -> String {
+    match legend {
+        true => export::add_legend(&rendered.svg, &rendered.legend),
+        false => rendered.svg.clone(),
+    }
+}
+
+fn poisoned<T>(_: T) -> std::io::Error {
+    std::io::Error::other("the editor stopped while rendering")
+}
+
+// Sends the machine whenever it looks different, until the browser goes
+// away.
+fn stream_events(mut stream: TcpStream, shared: &Shared, legend: bool) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\n\r\n"
+    )?;
+
+    let mut sent = None;
+    loop {
+        let rendered = shared.rendered.lock().map_err(poisoned)?;
+        let (rendered, _) = shared
+            .changed
+            .wait_timeout_while(rendered, KEEP_ALIVE, |r| Some(r.version) == sent)
+            .map_err(poisoned)?;
+
+        if Some(rendered.version) == sent {
+            drop(rendered);
+            stream.write_all(b": still here\n\n")?;
+            continue;
+        }
+        sent = Some(rendered.version);
+        let svg = svg(&rendered, legend);
+        drop(rendered);
+
+        // Every line of the data needs its own field.
+        let mut event = String::new();
+        for line in svg.lines() {
+            event.push_str("data: ");
+            event.push_str(line);
+            event.push('\n');
+        }
+        event.push('\n');
+        stream.write_all(event.as_bytes())?;
+    }
+}
+
+fn respond(stream: TcpStream, shared: &Shared) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let legend = query
+        .split('&')
+        .any(|p| p == "legend" || p.starts_with("legend="));
+    let (status, content_type, body) = match path {
+        "/" => ("200 OK", "text/html", PAGE.to_string()),
+        "/events" => return stream_events(reader.into_inner(), shared, legend),
+        "/machine.svg" => (
+            "200 OK",
+            "image/svg+xml",
+            svg(&*shared.rendered.lock().map_err(poisoned)?, legend),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found".to_string()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn serves_the_machine_on_a_free_port() {
+        let web_view = WebView::serve(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        assert_ne!(web_view.port, 0);
+
+        let machine = crate::import::mermaid("stateDiagram-v2\nDoor --> Door\n").unwrap();
+        web_view.update(
+            &machine.states,
+            &machine.initial,
+            ([0.0, 500.0], [0.0, 500.0]),
+            &StateStyle::default(),
+            &EdgeStyles::default(),
+            &machine.metadata,
+        );
+
+        let mut stream = TcpStream::connect(("127.0.0.1", web_view.port)).unwrap();
+        stream
+            .write_all(b"GET /machine.svg HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(">Door</text>"));
+    }
+}
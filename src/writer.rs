@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use crate::events::AppEvent;
+use crate::machine_file::Snapshot;
+use crate::storage;
+
+pub enum Job {
+    Save {
+        path: PathBuf,
+        machine: Snapshot,
+        snapshots: Vec<(String, Snapshot)>,
+    },
+    Export {
+        path: PathBuf,
+        contents: String,
+    },
+}
+
+// A finished job, handed back to the main loop.
+pub enum Written {
+    Saved {
+        path: PathBuf,
+        machine: Snapshot,
+        // The fingerprint of the file as saved.
+        result: std::io::Result<Option<u64>>,
+    },
+    Exported {
+        path: PathBuf,
+        result: std::io::Result<()>,
+    },
+}
+
+// Saves and exports are written on one thread of their own, so a big
+// machine or a slow server doesn't freeze the editor, and in the order they
+// were asked for, so an older save can't overwrite a newer one. Snapshots
+// are used as the machine itself can't leave the main thread.
+pub struct Writer {
+    jobs: Sender<Job>,
+    // Jobs not written yet.
+    pending: usize,
+}
+
+impl Writer {
+    pub fn new(events: Sender<std::io::Result<AppEvent>>) -> Self {
+        let (jobs, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for job in rx {
+                if events.send(Ok(AppEvent::Written(run(job)))).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { jobs, pending: 0 }
+    }
+
+    pub fn start(&mut self, job: Job) {
+        if self.jobs.send(job).is_ok() {
+            self.pending += 1;
+        }
+    }
+
+    pub fn finished(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.pending > 0
+    }
+}
+
+fn run(job: Job) -> Written {
+    match job {
+        Job::Save {
+            path,
+            machine,
+            snapshots,
+        } => {
+            let result = storage::backend(&path)
+                .and_then(|backend| {
+                    backend.save(&machine.restore())?;
+                    snapshots
+                        .iter()
+                        .try_for_each(|(name, snapshot)| backend.save_snapshot(name, snapshot))
+                })
+                .map(|()| storage::fingerprint(&path));
+            Written::Saved {
+                path,
+                machine,
+                result,
+            }
+        }
+        Job::Export { path, contents } => {
+            let result = std::fs::write(&path, contents);
+            Written::Exported { path, result }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_in_the_background() {
+        let (tx, rx) = mpsc::channel();
+        let mut writer = Writer::new(tx);
+        let dir = std::env::temp_dir();
+        let machine = crate::import::mermaid("stateDiagram-v2\n[*] --> A\nA --> B\n").unwrap();
+
+        let saved = dir.join(format!("fsmtui-{}-writer.json", std::process::id()));
+        let exported = dir.join(format!("fsmtui-{}-writer.dot", std::process::id()));
+        writer.start(Job::Save {
+            path: saved.clone(),
+            machine: Snapshot::take(&machine),
+            snapshots: vec![],
+        });
+        writer.start(Job::Export {
+            path: exported.clone(),
+            contents: "digraph {}".to_string(),
+        });
+        assert!(writer.is_busy());
+
+        // Jobs finish in the order they were started.
+        let Ok(Ok(AppEvent::Written(Written::Saved { result, .. }))) = rx.recv() else {
+            panic!("expected the save first");
+        };
+        assert!(result.unwrap().is_some());
+        let Ok(Ok(AppEvent::Written(Written::Exported { result, .. }))) = rx.recv() else {
+            panic!("expected the export second");
+        };
+        result.unwrap();
+        writer.finished();
+        writer.finished();
+        assert!(!writer.is_busy());
+
+        assert_eq!(crate::open(&saved).unwrap().states.len(), 2);
+        assert_eq!(std::fs::read_to_string(&exported).unwrap(), "digraph {}");
+        let _ = std::fs::remove_file(saved);
+        let _ = std::fs::remove_file(exported);
+    }
+}